@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { UsageByCondition } from "./UsageByCondition";
+
+export type AttributesByCondition = { derive_macros: UsageByCondition, attribute_macros: UsageByCondition, builtin_attribute_macros: UsageByCondition, derive_helpers: UsageByCondition, inner_attributes: UsageByCondition, };
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { MacroUsage } from "./MacroUsage";
+
+/**
+ * Attributes applied by `cfg_attr` when its predicate holds
+ */
+export type ConditionalAttributes = { derives: MacroUsage, attribute_macro_invocations: MacroUsage, builtin_attribute_macro_invocations: MacroUsage, derive_helper_attributes: MacroUsage, inner_attribute_invocations: MacroUsage, };
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ConditionalAttributes } from "./ConditionalAttributes";
+
+/**
+ * Conditional attributes by `cfg_attr` predicate, e.g. `feature = "serde"`
+ */
+export type ConditionalMacroUsage = { [key: string]: ConditionalAttributes };
@@ -0,0 +1,10 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { AttributesByCondition } from "./AttributesByCondition";
+import type { CharLineCount } from "./CharLineCount";
+import type { DeriveUsage } from "./DeriveUsage";
+import type { MacroAnalyzis } from "./MacroAnalyzis";
+import type { ProviderMacroUsage } from "./ProviderMacroUsage";
+import type { SourceClass } from "./SourceClass";
+import type { TargetKind } from "./TargetKind";
+
+export type Data = { macro_definitions_per_repo: Array<[string, number]>, macro_invocations_per_repo: Array<[string, number]>, macro_definitions_per_crate: Array<[string, number]>, macro_invocations_per_crate: Array<[string, number]>, lines_per_repo: { [key: string]: number }, lines_per_crate: { [key: string]: number }, characters_per_repo: { [key: string]: number }, characters_per_crate: { [key: string]: number }, derive_usage: DeriveUsage, total_macro_usage: MacroAnalyzis, attributes_by_condition: AttributesByCondition, macro_usage_per_target: { [key: TargetKind]: MacroAnalyzis }, macro_usage_per_class: { [key: SourceClass]: MacroAnalyzis }, macro_usage_per_provider: { [key: string]: ProviderMacroUsage }, source_count_per_class: { [key: SourceClass]: CharLineCount }, date: string, };
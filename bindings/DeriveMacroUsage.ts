@@ -1,3 +1,8 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { MacroUsage } from "./MacroUsage";
 
-export type DeriveMacroUsage = { count: number, avg: number, };
+export type DeriveMacroUsage = { derives_per_invocation: Array<number>, derives: MacroUsage, 
+/**
+ * `derives` by canonical path, e.g. `serde::Serialize`
+ */
+resolved_derives: MacroUsage, };
@@ -1,5 +1,34 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
-import type { AttributeMacroUsage } from "./AttributeMacroUsage";
+import type { ConditionalMacroUsage } from "./ConditionalMacroUsage";
 import type { DeriveMacroUsage } from "./DeriveMacroUsage";
+import type { MacroUsage } from "./MacroUsage";
 
-export type MacroAnalyzis = { attribute_macro_definition_count: number, declarative_macro_definition_count: number, procedural_macro_definition_count: number, derive_macro_definition_count: number, derive_macro_usage: DeriveMacroUsage, attribute_macro_invocation_count: number, attribute_macro_usage: AttributeMacroUsage, macro_invocation_count: number, };
+export type MacroAnalyzis = { attribute_macro_definitions: MacroUsage, declarative_macro_definitions: MacroUsage, procedural_macro_definitions: MacroUsage, derive_macro_definitions: MacroUsage, derive_macro_usage: DeriveMacroUsage, attribute_macro_invocations: MacroUsage, builtin_attribute_macro_invocations: MacroUsage, macro_invocations: MacroUsage, 
+/**
+ * Inert attributes of derive macros, e.g. `#[serde(..)]`, which are left out of
+ * `attribute_macro_invocations`
+ */
+derive_helper_attributes: MacroUsage, 
+/**
+ * Attributes of the enclosing module or crate, e.g. `#![no_std]`, built-in or not
+ */
+inner_attribute_invocations: MacroUsage, 
+/**
+ * Invocations by canonical path, resolved through the `use` declarations of each
+ * file, while the fields above keep the spelling as written
+ */
+resolved_attribute_macro_invocations: MacroUsage, resolved_macro_invocations: MacroUsage, 
+/**
+ * Attributes and derives wrapped in `cfg_attr`, which the fields above only count
+ * as a `cfg_attr` built-in attribute
+ */
+conditional_attributes: ConditionalMacroUsage, 
+/**
+ * Function-like invocations written in the arguments of other invocations, e.g.
+ * `format!` in `vec![format!(..)]`, which are left out of `macro_invocations`
+ */
+nested_macro_invocations: MacroUsage, 
+/**
+ * Function-like invocations written in the rules of `macro_rules!` definitions
+ */
+macro_definition_invocations: MacroUsage, };
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Number of derives, attribute macro and function-like macro invocations of the
+ * macros of a crate
+ */
+export type ProviderMacroUsage = { derives: number, attribute_macro_invocations: number, macro_invocations: number, };
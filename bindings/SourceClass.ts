@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Whether a source file was written for the project, copied from another one or generated
+ */
+export type SourceClass = "authored" | "vendored" | "generated";
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Kind of Cargo target a source file is compiled into
+ */
+export type TargetKind = "lib" | "proc_macro" | "bin" | "example" | "test" | "bench" | "build" | "other";
@@ -1,13 +1,25 @@
-use std::{collections::HashMap, fs, path::Path};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
 
 use crate::{
     crate_paths::get_repo_path,
     error::{Error, ErrorMessage},
-    results::AnalyzisResults,
-    state::ScraperState,
-    utils::{parse_file, pretty_print, BUILTIN_ATTRIBUTES, FILES_TO_IGNORE, FOLDERS_TO_IGNORE},
+    github::CloneReport,
+    ignore::{collect_rust_files, IgnoreRules},
+    macro_paths::MacroScope,
+    module_tree::{collect_module_tree, FileCollection},
+    providers::{merge_providers_usage, CrateDependencies},
+    results::{merge_source_count, AnalyzisResults, CrateAnalyzis},
+    source_class::{classify_file, SourceClass},
+    state::{ScraperState, Stage},
+    targets::{crate_targets, CrateTargets, TargetKind},
+    utils::{parse_file, pretty_print, BUILTIN_ATTRIBUTES, DERIVE_HELPER_ATTRIBUTES},
+    workspace::Workspace,
 };
-use chrono::Local;
+use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
 use tree_sitter::Node;
 use ts_rs::TS;
@@ -16,11 +28,17 @@ use ts_rs::TS;
 pub struct DeriveMacroUsage {
     pub derives_per_invocation: Vec<usize>,
     pub derives: MacroUsage,
+    /// `derives` by canonical path, e.g. `serde::Serialize`
+    #[serde(default)]
+    pub resolved_derives: MacroUsage,
 }
 
 impl DeriveMacroUsage {
-    pub fn add_point(&mut self, derives: Vec<String>) {
+    pub fn add_point(&mut self, derives: Vec<String>, scope: &MacroScope) {
         self.derives_per_invocation.push(derives.len());
+        for derive in derives.iter() {
+            self.resolved_derives += &scope.resolve_derive(derive);
+        }
         self.derives.add_multiple(derives);
     }
 }
@@ -33,6 +51,7 @@ impl std::ops::Add for DeriveMacroUsage {
             derives_per_invocation: [self.derives_per_invocation, rhs.derives_per_invocation]
                 .concat(),
             derives: self.derives + rhs.derives,
+            resolved_derives: self.resolved_derives + rhs.resolved_derives,
         }
     }
 }
@@ -49,12 +68,8 @@ impl From<MacroUsage> for u32 {
 
 impl MacroUsage {
     fn add_builtin(&mut self, value: &str) -> Option<()> {
-        if value.starts_with("rustfmt::") || value.starts_with("clippy::") {
-            let prev = self.0.get("rustfmt::").unwrap_or(&0);
-            self.0.insert("rustfmt::".to_string(), prev + 1);
-            return Some(());
-        }
-        if BUILTIN_ATTRIBUTES.iter().any(|v| *v == value) {
+        let is_tool = value.starts_with("rustfmt::") || value.starts_with("clippy::");
+        if is_tool || BUILTIN_ATTRIBUTES.contains(&value) {
             let prev = self.0.get(value).unwrap_or(&0);
             self.0.insert(value.to_string(), prev + 1);
             return Some(());
@@ -92,6 +107,50 @@ impl std::ops::Add for MacroUsage {
     }
 }
 
+/// Attributes applied by `cfg_attr` when its predicate holds
+#[derive(TS, Serialize, Deserialize, Default, Debug, Clone)]
+pub struct ConditionalAttributes {
+    pub derives: MacroUsage,
+    pub attribute_macro_invocations: MacroUsage,
+    pub builtin_attribute_macro_invocations: MacroUsage,
+    pub derive_helper_attributes: MacroUsage,
+    pub inner_attribute_invocations: MacroUsage,
+}
+
+impl std::ops::Add for ConditionalAttributes {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self {
+            derives: self.derives + rhs.derives,
+            attribute_macro_invocations: self.attribute_macro_invocations
+                + rhs.attribute_macro_invocations,
+            builtin_attribute_macro_invocations: self.builtin_attribute_macro_invocations
+                + rhs.builtin_attribute_macro_invocations,
+            derive_helper_attributes: self.derive_helper_attributes + rhs.derive_helper_attributes,
+            inner_attribute_invocations: self.inner_attribute_invocations
+                + rhs.inner_attribute_invocations,
+        }
+    }
+}
+
+/// Conditional attributes by `cfg_attr` predicate, e.g. `feature = "serde"`
+#[derive(TS, Serialize, Deserialize, Default, Debug, Clone)]
+pub struct ConditionalMacroUsage(pub HashMap<String, ConditionalAttributes>);
+
+impl std::ops::Add for ConditionalMacroUsage {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        let mut result = self.0;
+        for (predicate, attributes) in rhs.0 {
+            let prev = result.remove(&predicate).unwrap_or_default();
+            result.insert(predicate, prev + attributes);
+        }
+        Self(result)
+    }
+}
+
 #[derive(TS, Serialize, Deserialize, Default, Debug, Clone)]
 pub struct MacroAnalyzis {
     pub attribute_macro_definitions: MacroUsage,
@@ -104,6 +163,33 @@ pub struct MacroAnalyzis {
     pub attribute_macro_invocations: MacroUsage,
     pub builtin_attribute_macro_invocations: MacroUsage,
     pub macro_invocations: MacroUsage,
+    /// Inert attributes of derive macros, e.g. `#[serde(..)]`, which are left out of
+    /// `attribute_macro_invocations`
+    #[serde(default)]
+    pub derive_helper_attributes: MacroUsage,
+    /// Attributes of the enclosing module or crate, e.g. `#![no_std]`, built-in or not
+    #[serde(default)]
+    pub inner_attribute_invocations: MacroUsage,
+
+    /// Invocations by canonical path, resolved through the `use` declarations of each
+    /// file, while the fields above keep the spelling as written
+    #[serde(default)]
+    pub resolved_attribute_macro_invocations: MacroUsage,
+    #[serde(default)]
+    pub resolved_macro_invocations: MacroUsage,
+
+    /// Attributes and derives wrapped in `cfg_attr`, which the fields above only count
+    /// as a `cfg_attr` built-in attribute
+    #[serde(default)]
+    pub conditional_attributes: ConditionalMacroUsage,
+
+    /// Function-like invocations written in the arguments of other invocations, e.g.
+    /// `format!` in `vec![format!(..)]`, which are left out of `macro_invocations`
+    #[serde(default)]
+    pub nested_macro_invocations: MacroUsage,
+    /// Function-like invocations written in the rules of `macro_rules!` definitions
+    #[serde(default)]
+    pub macro_definition_invocations: MacroUsage,
 }
 
 impl std::ops::Add for MacroAnalyzis {
@@ -124,10 +210,55 @@ impl std::ops::Add for MacroAnalyzis {
                 + rhs.attribute_macro_definitions,
             derive_macro_usage: self.derive_macro_usage + rhs.derive_macro_usage,
             macro_invocations: self.macro_invocations + rhs.macro_invocations,
+            derive_helper_attributes: self.derive_helper_attributes + rhs.derive_helper_attributes,
+            inner_attribute_invocations: self.inner_attribute_invocations
+                + rhs.inner_attribute_invocations,
+            resolved_attribute_macro_invocations: self.resolved_attribute_macro_invocations
+                + rhs.resolved_attribute_macro_invocations,
+            resolved_macro_invocations: self.resolved_macro_invocations
+                + rhs.resolved_macro_invocations,
+            conditional_attributes: self.conditional_attributes + rhs.conditional_attributes,
+            nested_macro_invocations: self.nested_macro_invocations + rhs.nested_macro_invocations,
+            macro_definition_invocations: self.macro_definition_invocations
+                + rhs.macro_definition_invocations,
         }
     }
 }
 
+/// Macro usage of the files of every Cargo target kind
+pub type TargetMacroUsage = BTreeMap<TargetKind, MacroAnalyzis>;
+
+/// Macro usage of authored, vendored and generated files
+pub type ClassMacroUsage = BTreeMap<SourceClass, MacroAnalyzis>;
+
+/// Adds the macro usage of every target kind or source class
+pub fn merge_macro_usage<K: Ord + Copy>(
+    usage: &mut BTreeMap<K, MacroAnalyzis>,
+    rhs: &BTreeMap<K, MacroAnalyzis>,
+) {
+    for (key, analyzis) in rhs {
+        let prev = usage.remove(key).unwrap_or_default();
+        usage.insert(*key, prev + analyzis.clone());
+    }
+}
+
+// Macro usage of the files of a crate, by target kind and by source class
+#[derive(Default)]
+struct CrateMacroUsage {
+    targets: TargetMacroUsage,
+    classes: ClassMacroUsage,
+}
+
+impl CrateMacroUsage {
+    fn add(&mut self, kind: TargetKind, class: SourceClass, analyzis: MacroAnalyzis) {
+        merge_macro_usage(
+            &mut self.targets,
+            &BTreeMap::from([(kind, analyzis.clone())]),
+        );
+        merge_macro_usage(&mut self.classes, &BTreeMap::from([(class, analyzis)]));
+    }
+}
+
 fn find_next_non_macro(node: Node) -> Option<Node> {
     let next = match node.next_sibling() {
         Some(next) => next,
@@ -139,7 +270,215 @@ fn find_next_non_macro(node: Node) -> Option<Node> {
     Some(next)
 }
 
-fn count_macro_usage(root: Node, bytes: &[u8]) -> Result<MacroAnalyzis, Error> {
+// Tokens a macro path is made of, e.g. `$crate::vec`, where `$crate` is a metavariable
+const PATH_TOKENS: [&str; 6] = ["identifier", "::", "crate", "self", "super", "metavariable"];
+
+// Token trees aren't parsed, so invocations in them are found as a path followed by
+// `!` and a delimited token tree, in every nested token tree
+fn count_token_tree_invocations(token_tree: Node, bytes: &[u8], usage: &mut MacroUsage) {
+    let tokens: Vec<Node> = token_tree.children(&mut token_tree.walk()).collect();
+    let mut path_start = None;
+    for (i, token) in tokens.iter().enumerate() {
+        match token.kind() {
+            kind if PATH_TOKENS.contains(&kind) => {
+                // A segment only continues the path after `::`, so that `foo bar!(..)`
+                // is recorded as `bar`
+                let continues = i > 0 && tokens[i - 1].kind() == "::";
+                if kind == "::" || continues {
+                    path_start.get_or_insert(i);
+                } else {
+                    path_start = Some(i);
+                }
+                continue;
+            }
+            "!" => {
+                let delimited = tokens.get(i + 1).map(|next| next.kind()) == Some("token_tree");
+                if let Some(start) = path_start.filter(|_| delimited) {
+                    // `#name!(..)` interpolates a variable in `quote!`
+                    let interpolated = start > 0 && tokens[start - 1].kind() == "#";
+                    if tokens[i - 1].kind() == "identifier" && !interpolated {
+                        let value = &bytes[tokens[start].start_byte()..tokens[i - 1].end_byte()];
+                        *usage += std::str::from_utf8(value).unwrap();
+                    }
+                }
+            }
+            "token_tree" => count_token_tree_invocations(*token, bytes, usage),
+            _ => (),
+        }
+        path_start = None;
+    }
+}
+
+// Attributes of fields and variants can't be attribute macros, only inert ones
+const FIELD_LISTS: [&str; 3] = [
+    "field_declaration_list",
+    "ordered_field_declaration_list",
+    "enum_variant_list",
+];
+
+fn is_attached(node: &Node) -> bool {
+    matches!(
+        node.kind(),
+        "attribute_item" | "line_comment" | "block_comment"
+    )
+}
+
+// Attributes and comments around the attribute, all applying to the same item
+fn attached_attributes(attribute_item: Node) -> Vec<Node> {
+    let mut attached = vec![attribute_item];
+    let mut prev = attribute_item.prev_sibling();
+    while let Some(sibling) = prev.filter(is_attached) {
+        attached.push(sibling);
+        prev = sibling.prev_sibling();
+    }
+    let mut next = attribute_item.next_sibling();
+    while let Some(sibling) = next.filter(is_attached) {
+        attached.push(sibling);
+        next = sibling.next_sibling();
+    }
+    attached
+}
+
+// Derives of `derive(..)` tokens, or of the attributes of `cfg_attr(<predicate>, ..)`
+fn add_derives(tokens: &[Node], bytes: &[u8], derives: &mut Vec<String>) {
+    let (Some(path), Some(arguments)) = (tokens.first(), tokens.get(1)) else {
+        return;
+    };
+    if arguments.kind() != "token_tree" {
+        return;
+    }
+    match &bytes[path.byte_range()] {
+        b"derive" => derives.extend(derive_paths(*arguments, bytes)),
+        b"cfg_attr" => {
+            for group in split_token_tree(*arguments).iter().skip(1) {
+                add_derives(group, bytes, derives);
+            }
+        }
+        _ => (),
+    }
+}
+
+// Derives of the attributes, including the conditional ones
+fn attribute_derives(attribute_items: &[Node], bytes: &[u8]) -> Vec<String> {
+    let mut derives = Vec::new();
+    for attribute_item in attribute_items {
+        let Some(attribute) = attribute_item
+            .children(&mut attribute_item.walk())
+            .find(|node| node.kind() == "attribute")
+        else {
+            continue;
+        };
+        let tokens: Vec<Node> = attribute.children(&mut attribute.walk()).collect();
+        add_derives(&tokens, bytes, &mut derives);
+    }
+    derives
+}
+
+// Attributes of fields and variants can't be attribute macros, so they're helpers of a
+// derive or of an attribute macro of the item. Others are only helpers next to a derive
+fn is_derive_helper(attribute_item: Node, value: &str, bytes: &[u8]) -> bool {
+    if attribute_item
+        .parent()
+        .is_some_and(|parent| FIELD_LISTS.contains(&parent.kind()))
+    {
+        return true;
+    }
+    DERIVE_HELPER_ATTRIBUTES.contains(&value)
+        && !attribute_derives(&attached_attributes(attribute_item), bytes).is_empty()
+}
+
+// Splits the tokens of a token tree on its top level commas, without the delimiters
+fn split_token_tree(token_tree: Node) -> Vec<Vec<Node>> {
+    let mut groups = vec![Vec::new()];
+    for token in token_tree.children(&mut token_tree.walk()) {
+        match token.kind() {
+            "(" | ")" | "[" | "]" | "{" | "}" => (),
+            "," => groups.push(Vec::new()),
+            _ => groups.last_mut().unwrap().push(token),
+        }
+    }
+    groups
+}
+
+fn tokens_text(tokens: &[Node], bytes: &[u8]) -> String {
+    tokens
+        .iter()
+        .map(|token| std::str::from_utf8(&bytes[token.byte_range()]).unwrap())
+        .collect()
+}
+
+// Derives are separated by commas and may be paths, e.g. `serde::Serialize`
+fn derive_paths(token_tree: Node, bytes: &[u8]) -> Vec<String> {
+    split_token_tree(token_tree)
+        .iter()
+        .map(|tokens| tokens_text(tokens, bytes))
+        .filter(|derive| !derive.is_empty())
+        .collect()
+}
+
+// Counts the attributes of `cfg_attr(<predicate>, <attribute>, ..)` under its
+// predicate, with the source spacing collapsed. Nested `cfg_attr` require every
+// predicate, as `all(<outer>, <inner>)`
+fn count_cfg_attr(
+    attribute_item: Node,
+    token_tree: Node,
+    outer_predicate: Option<&str>,
+    bytes: &[u8],
+    usage: &mut ConditionalMacroUsage,
+) {
+    let mut groups = split_token_tree(token_tree).into_iter();
+    let predicate = match groups.next() {
+        Some(tokens) if !tokens.is_empty() => {
+            let range = tokens[0].start_byte()..tokens[tokens.len() - 1].end_byte();
+            let text = std::str::from_utf8(&bytes[range]).unwrap();
+            text.split_whitespace().collect::<Vec<_>>().join(" ")
+        }
+        _ => return,
+    };
+    let predicate = match outer_predicate {
+        Some(outer) => format!("all({}, {})", outer, predicate),
+        None => predicate,
+    };
+
+    for tokens in groups {
+        let path_len = tokens
+            .iter()
+            .position(|token| token.kind() == "token_tree" || token.kind() == "=")
+            .unwrap_or(tokens.len());
+        let value = tokens_text(&tokens[..path_len], bytes);
+        if value.is_empty() {
+            continue;
+        }
+        let arguments = tokens
+            .get(path_len)
+            .filter(|token| token.kind() == "token_tree");
+        if let ("cfg_attr", Some(arguments)) = (value.as_str(), arguments) {
+            count_cfg_attr(attribute_item, *arguments, Some(&predicate), bytes, usage);
+            continue;
+        }
+
+        let attributes = usage.0.entry(predicate.clone()).or_default();
+        if attribute_item.kind() == "inner_attribute_item" {
+            attributes.inner_attribute_invocations += &value;
+        } else if attributes
+            .builtin_attribute_macro_invocations
+            .add_builtin(&value)
+            .is_some()
+        {
+            if let ("derive", Some(arguments)) = (value.as_str(), arguments) {
+                attributes
+                    .derives
+                    .add_multiple(derive_paths(*arguments, bytes));
+            }
+        } else if is_derive_helper(attribute_item, &value, bytes) {
+            attributes.derive_helper_attributes += &value;
+        } else {
+            attributes.attribute_macro_invocations += &value;
+        }
+    }
+}
+
+fn count_macro_usage(root: Node, bytes: &[u8], scope: &MacroScope) -> Result<MacroAnalyzis, Error> {
     let mut analyzis = MacroAnalyzis::default();
     let mut ignore_next = false;
     for node in root.children(&mut root.walk()) {
@@ -163,6 +502,16 @@ fn count_macro_usage(root: Node, bytes: &[u8]) -> Result<MacroAnalyzis, Error> {
             let value = &bytes[identifier.byte_range()];
             let value = String::from_utf8(value.to_vec()).unwrap();
             analyzis.declarative_macro_definitions += &value;
+
+            for rule in node.children(&mut node.walk()) {
+                if let Some(token_tree) = rule.child_by_field_name("right") {
+                    count_token_tree_invocations(
+                        token_tree,
+                        bytes,
+                        &mut analyzis.macro_definition_invocations,
+                    );
+                }
+            }
         }
 
         // Invocation of function-like macros and declarative macros
@@ -180,7 +529,42 @@ fn count_macro_usage(root: Node, bytes: &[u8]) -> Result<MacroAnalyzis, Error> {
             };
             let value = &bytes[identifier.byte_range()];
             let value = String::from_utf8(value.to_vec()).unwrap();
+            analyzis.resolved_macro_invocations += &scope.resolve_macro(&value);
             analyzis.macro_invocations += &value;
+
+            if let Some(token_tree) = node
+                .children(&mut node.walk())
+                .find(|n| n.kind() == "token_tree")
+            {
+                count_token_tree_invocations(
+                    token_tree,
+                    bytes,
+                    &mut analyzis.nested_macro_invocations,
+                );
+            }
+        }
+
+        // Attributes of the enclosing module or crate
+        if node.kind() == "inner_attribute_item" {
+            if let Some(identifier) = node
+                .children(&mut node.walk())
+                .find(|n| n.kind() == "attribute")
+                .and_then(|attribute| attribute.child(0))
+            {
+                let value = std::str::from_utf8(&bytes[identifier.byte_range()]).unwrap();
+                analyzis.inner_attribute_invocations += value;
+                if value == "cfg_attr" {
+                    if let Some(token_tree) = identifier.next_named_sibling() {
+                        count_cfg_attr(
+                            node,
+                            token_tree,
+                            None,
+                            bytes,
+                            &mut analyzis.conditional_attributes,
+                        );
+                    }
+                }
+            }
         }
 
         // Handling attributes
@@ -200,7 +584,10 @@ fn count_macro_usage(root: Node, bytes: &[u8]) -> Result<MacroAnalyzis, Error> {
             let is_builtin = analyzis
                 .builtin_attribute_macro_invocations
                 .add_builtin(&value);
-            if is_builtin.is_none() {
+            if is_builtin.is_none() && is_derive_helper(node, &value, bytes) {
+                analyzis.derive_helper_attributes += &value;
+            } else if is_builtin.is_none() {
+                analyzis.resolved_attribute_macro_invocations += &scope.resolve_attribute(&value);
                 analyzis.attribute_macro_invocations += &value;
             }
 
@@ -287,19 +674,25 @@ fn count_macro_usage(root: Node, bytes: &[u8]) -> Result<MacroAnalyzis, Error> {
                         });
                     }
                 };
-                let derives: Vec<String> = token_tree
-                    .children(&mut token_tree.walk())
-                    .filter(|n| n.kind() == "identifier")
-                    .map(|identifier| {
-                        let value = &bytes[identifier.byte_range()];
-                        String::from_utf8(value.to_vec()).unwrap()
-                    })
-                    .collect();
-                analyzis.derive_macro_usage.add_point(derives);
+                analyzis
+                    .derive_macro_usage
+                    .add_point(derive_paths(token_tree, bytes), scope);
+            }
+            // Attributes applied only when the predicate holds
+            if value == "cfg_attr" {
+                if let Some(token_tree) = attribute.child_by_field_name("arguments") {
+                    count_cfg_attr(
+                        node,
+                        token_tree,
+                        None,
+                        bytes,
+                        &mut analyzis.conditional_attributes,
+                    );
+                }
             }
         }
         if node.child_count() > 0 {
-            let res = count_macro_usage(node, bytes)?;
+            let res = count_macro_usage(node, bytes, scope)?;
             analyzis = analyzis + res;
         }
     }
@@ -307,49 +700,59 @@ fn count_macro_usage(root: Node, bytes: &[u8]) -> Result<MacroAnalyzis, Error> {
     Ok(analyzis)
 }
 
-fn count_dir_macro_usage(path: &Path) -> Result<MacroAnalyzis, Error> {
-    let mut analyzis = MacroAnalyzis::default();
-    let folders_to_ignore = FOLDERS_TO_IGNORE.map(std::ffi::OsStr::new);
-    let files_to_ignore = FILES_TO_IGNORE.map(std::ffi::OsStr::new);
-    let entries = fs::read_dir(path).map_err(|_| Error {
+fn count_file_macro_usage(
+    path: &Path,
+    kind: TargetKind,
+    repo_dir: &Path,
+    usage: &mut CrateMacroUsage,
+) -> Result<(), Error> {
+    match fs::read_to_string(path) {
+        Ok(string) => {
+            let bytes = string.as_bytes();
+            let tree = parse_file(bytes);
+            let scope = MacroScope::from_tree(tree.root_node(), bytes);
+            let result = count_macro_usage(tree.root_node(), bytes, &scope)
+                .map_err(|err| err.add_path(path.to_str().unwrap()))?;
+            let class = classify_file(path.strip_prefix(repo_dir).unwrap_or(path), &string);
+            usage.add(kind, class, result);
+        }
+        Err(e) => {
+            println!("Failed to read file of path {:?}. Error: {}", path, e);
+        }
+    }
+    Ok(())
+}
+
+fn count_dir_macro_usage(
+    path: &Path,
+    targets: &CrateTargets,
+    rules: &IgnoreRules,
+    repo_dir: &Path,
+    usage: &mut CrateMacroUsage,
+    ignored: &mut Vec<PathBuf>,
+) -> Result<(), Error> {
+    let mut files = Vec::new();
+    collect_rust_files(path, rules, &mut files, ignored).map_err(|_| Error {
         path: Some(path.display().to_string()),
         message: ErrorMessage::FailedToReadDirectory,
     })?;
-    for entry in entries {
-        let entry = entry.unwrap();
-        let path = entry.path();
-        let name = entry.file_name();
-
-        if path.is_dir() {
-            if folders_to_ignore.iter().any(|v| *v == name) {
-                continue;
-            }
-            let output = count_dir_macro_usage(&path)?;
-            analyzis = analyzis + output;
-        }
-
-        let file_name = path.file_name();
-        if let Some(file_name) = file_name {
-            if files_to_ignore.iter().any(|v| *v == file_name) {
-                continue;
-            }
-            if path.is_file() && path.extension() == Some(std::ffi::OsStr::new("rs")) {
-                match fs::read_to_string(&path) {
-                    Ok(string) => {
-                        let bytes = string.as_bytes();
-                        let tree = parse_file(bytes);
-                        let result = count_macro_usage(tree.root_node(), bytes)
-                            .map_err(|err| err.add_path(path.to_str().unwrap()))?;
-                        analyzis = analyzis + result;
-                    }
-                    Err(e) => {
-                        println!("Failed to read file of path {:?}. Error: {}", path, e);
-                    }
-                }
-            }
-        }
+    for file in files {
+        count_file_macro_usage(&file, targets.kind_of(&file), repo_dir, usage)?;
     }
-    Ok(analyzis)
+    Ok(())
+}
+
+// Paths of the files relative to the crate directory
+fn relative_paths(crate_dir: &Path, paths: Vec<PathBuf>) -> Vec<String> {
+    paths
+        .iter()
+        .map(|path| {
+            path.strip_prefix(crate_dir)
+                .unwrap_or(path)
+                .to_string_lossy()
+                .to_string()
+        })
+        .collect()
 }
 
 pub fn calculate_overall(results: &mut AnalyzisResults) {
@@ -360,16 +763,135 @@ pub fn calculate_overall(results: &mut AnalyzisResults) {
                 .expect("Expected repo to have macro_usage by here")
                 + results.overall.macro_usage.clone().unwrap_or_default(),
         );
+        let mut target_usage = results
+            .overall
+            .target_macro_usage
+            .take()
+            .unwrap_or_default();
+        merge_macro_usage(
+            &mut target_usage,
+            &repo.target_macro_usage.clone().unwrap_or_default(),
+        );
+        results.overall.target_macro_usage = Some(target_usage);
+        let mut class_usage = results.overall.class_macro_usage.take().unwrap_or_default();
+        merge_macro_usage(
+            &mut class_usage,
+            &repo.class_macro_usage.clone().unwrap_or_default(),
+        );
+        results.overall.class_macro_usage = Some(class_usage);
+        let mut provider_usage = results
+            .overall
+            .provider_macro_usage
+            .take()
+            .unwrap_or_default();
+        merge_providers_usage(
+            &mut provider_usage,
+            &repo.provider_macro_usage.clone().unwrap_or_default(),
+        );
+        results.overall.provider_macro_usage = Some(provider_usage);
         results.overall.source_count = Some(
             results.overall.source_count.unwrap_or_default()
                 + repo
                     .source_count
                     .expect("Expected repo to have source count by here"),
         );
+        let mut class_count = results
+            .overall
+            .class_source_count
+            .take()
+            .unwrap_or_default();
+        merge_source_count(
+            &mut class_count,
+            &repo.class_source_count.clone().unwrap_or_default(),
+        );
+        results.overall.class_source_count = Some(class_count);
     }
 }
 
+/// Macro usage of the last analysis, reused for the repositories that the following
+/// clone left unchanged
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct PreviousAnalyzis {
+    /// Clone the analysis ran on
+    pub cloned_at: Option<DateTime<Local>>,
+    pub file_collection: FileCollection,
+    pub crates: HashMap<String, CrateAnalyzis>,
+}
+
+impl_save_load!(PreviousAnalyzis, Workspace::previous_results_path);
+
+// Crates of the repositories left unchanged by the clone since the previous analysis
+fn unchanged_crates(workspace: &Workspace, state: &ScraperState) -> HashMap<String, CrateAnalyzis> {
+    let Some(previous) = PreviousAnalyzis::load(workspace) else {
+        return HashMap::new();
+    };
+    // Analyzing again without cloning, or differently, starts over
+    if previous.cloned_at == state.cloned_repos_at
+        || previous.file_collection != state.file_collection
+    {
+        return HashMap::new();
+    }
+    let report = CloneReport::load(workspace).unwrap_or_default();
+    previous
+        .crates
+        .into_iter()
+        .filter(|(crate_path, analyzis)| {
+            analyzis.macro_usage.is_some() && report.is_unchanged(&get_repo_path(crate_path))
+        })
+        .collect()
+}
+
+// Macro usage fields of the crate analyzis
+fn analyze_crate(
+    workspace: &Workspace,
+    file_collection: FileCollection,
+    crate_path: &str,
+    crate_analyzis: &CrateAnalyzis,
+) -> Result<CrateAnalyzis, Box<dyn std::error::Error>> {
+    let crate_dir = workspace.crate_dir(crate_path);
+    let repo_dir = workspace.repo_dir(&get_repo_path(crate_path));
+    let targets = crate_targets(&crate_dir, crate_analyzis.package.as_ref());
+    let mut usage = CrateMacroUsage::default();
+    let rules = IgnoreRules::for_crate(workspace, crate_path);
+    let mut unreachable_files = None;
+    let mut ignored = Vec::new();
+    match file_collection {
+        FileCollection::Walk => count_dir_macro_usage(
+            &crate_dir,
+            &CrateTargets::new(&crate_dir, &targets),
+            &rules,
+            &repo_dir,
+            &mut usage,
+            &mut ignored,
+        )?,
+        FileCollection::ModuleTree => {
+            let module_tree = collect_module_tree(&crate_dir, &targets, &rules);
+            for (path, kind) in &module_tree.files {
+                count_file_macro_usage(path, *kind, &repo_dir, &mut usage)?;
+            }
+            unreachable_files = Some(relative_paths(&crate_dir, module_tree.unreachable));
+            ignored = module_tree.ignored;
+        }
+    }
+    let analyzis = usage
+        .targets
+        .values()
+        .cloned()
+        .fold(MacroAnalyzis::default(), |acc, usage| acc + usage);
+    let provider_usage = CrateDependencies::read(&crate_dir).providers_usage(&analyzis);
+    Ok(CrateAnalyzis {
+        macro_usage: Some(analyzis),
+        target_macro_usage: Some(usage.targets),
+        class_macro_usage: Some(usage.classes),
+        provider_macro_usage: Some(provider_usage),
+        unreachable_files,
+        ignored_paths: Some(relative_paths(&crate_dir, ignored)),
+        ..Default::default()
+    })
+}
+
 pub fn analyze_crates(
+    workspace: &Workspace,
     state: &mut ScraperState,
     results: &mut AnalyzisResults,
 ) -> Result<(), Box<dyn std::error::Error>> {
@@ -380,22 +902,267 @@ pub fn analyze_crates(
         );
         return Ok(());
     }
+    results.clear_macro_usage();
 
-    for crate_path in results.crates.clone().keys() {
-        let repo_path = get_repo_path(crate_path);
-        let analyzis = count_dir_macro_usage(&Path::new("./data/repos").join(crate_path))?;
+    let mut unchanged = unchanged_crates(workspace, state);
+    let mut reused_count = 0;
+    let mut unreachable_files_count = 0;
+    let mut ignored_paths_count = 0;
+    for (crate_path, crate_analyzis) in results.crates.clone() {
+        let repo_path = get_repo_path(&crate_path);
+        let analyzed = match unchanged.remove(&crate_path) {
+            Some(analyzed) => {
+                reused_count += 1;
+                analyzed
+            }
+            None => analyze_crate(
+                workspace,
+                state.file_collection,
+                &crate_path,
+                &crate_analyzis,
+            )?,
+        };
+        unreachable_files_count += analyzed.unreachable_files.as_ref().map_or(0, Vec::len);
+        ignored_paths_count += analyzed.ignored_paths.as_ref().map_or(0, Vec::len);
+        let analyzis = analyzed.macro_usage.clone().unwrap_or_default();
+        let target_usage = analyzed.target_macro_usage.clone().unwrap_or_default();
+        let class_usage = analyzed.class_macro_usage.clone().unwrap_or_default();
+        let provider_usage = analyzed.provider_macro_usage.clone().unwrap_or_default();
 
-        results.update_crate(crate_path, &mut |crate_analyzis| {
-            crate_analyzis.macro_usage = Some(analyzis.clone());
+        results.update_crate(&crate_path, &mut |crate_analyzis| {
+            crate_analyzis.macro_usage = analyzed.macro_usage.clone();
+            crate_analyzis.target_macro_usage = analyzed.target_macro_usage.clone();
+            crate_analyzis.class_macro_usage = analyzed.class_macro_usage.clone();
+            crate_analyzis.provider_macro_usage = analyzed.provider_macro_usage.clone();
+            crate_analyzis.unreachable_files = analyzed.unreachable_files.clone();
+            crate_analyzis.ignored_paths = analyzed.ignored_paths.clone();
         });
         results.update_repo(&repo_path, &mut |repo_analyzis| {
             let prev = repo_analyzis.macro_usage.clone().unwrap_or_default();
             repo_analyzis.macro_usage = Some(prev + analyzis.clone());
+            let mut targets = repo_analyzis.target_macro_usage.take().unwrap_or_default();
+            merge_macro_usage(&mut targets, &target_usage);
+            repo_analyzis.target_macro_usage = Some(targets);
+            let mut classes = repo_analyzis.class_macro_usage.take().unwrap_or_default();
+            merge_macro_usage(&mut classes, &class_usage);
+            repo_analyzis.class_macro_usage = Some(classes);
+            let mut providers = repo_analyzis
+                .provider_macro_usage
+                .take()
+                .unwrap_or_default();
+            merge_providers_usage(&mut providers, &provider_usage);
+            repo_analyzis.provider_macro_usage = Some(providers);
         })
     }
 
-    state.analyzed_macros_at = Some(Local::now());
-    state.save()?;
+    if reused_count > 0 {
+        pretty_print(
+            "Crates of unchanged repositories reused",
+            Some(&reused_count),
+        );
+    }
+    pretty_print("Ignored paths", Some(&ignored_paths_count));
+    if state.file_collection == FileCollection::ModuleTree {
+        pretty_print("Unreachable files", Some(&unreachable_files_count));
+    }
+    PreviousAnalyzis {
+        cloned_at: state.cloned_repos_at,
+        file_collection: state.file_collection,
+        crates: results.crates.clone(),
+    }
+    .save(workspace)?;
+    state.complete(workspace, Stage::Analyze);
+    state.save(workspace)?;
     pretty_print("Macros analyzed", None);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn analyze(source: &str) -> MacroAnalyzis {
+        let bytes = source.as_bytes();
+        let tree = parse_file(bytes);
+        let scope = MacroScope::from_tree(tree.root_node(), bytes);
+        count_macro_usage(tree.root_node(), bytes, &scope).unwrap()
+    }
+
+    fn names(usage: &MacroUsage) -> Vec<&str> {
+        let mut names: Vec<&str> = usage.0.keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn counts_nested_invocations_by_their_path_only() {
+        let analyzis = analyze(
+            "fn main() { outer!(foo bar!(1), a::b!(2), ::c::d!(3), quote!(#e!(4)), x y::z!()); }",
+        );
+        assert_eq!(
+            names(&analyzis.nested_macro_invocations),
+            ["::c::d", "a::b", "bar", "quote", "y::z"]
+        );
+    }
+
+    #[test]
+    fn counts_crate_relative_invocations_in_macro_rules() {
+        let analyzis = analyze("macro_rules! m { () => { let x = $crate::inner!(); vec![]; }; }");
+        assert_eq!(
+            names(&analyzis.macro_definition_invocations),
+            ["$crate::inner", "vec"]
+        );
+    }
+
+    #[test]
+    fn counts_workspace_members_in_their_own_crate_only() {
+        let root = tempfile::tempdir().unwrap();
+        let workspace = Workspace::new(root.path());
+        let repo_dir = workspace.repo_dir("repo");
+        let files = [
+            (
+                "Cargo.toml",
+                "[package]\nname = \"root\"\nversion = \"0.1.0\"\n\n[workspace]\nmembers = [\"member\"]\n",
+            ),
+            ("src/main.rs", "fn main() {}"),
+            (
+                "member/Cargo.toml",
+                "[package]\nname = \"member\"\nversion = \"0.1.0\"\n",
+            ),
+            (
+                "member/src/lib.rs",
+                "#[derive(Clone)]\nstruct A;\n\nfn f() { println!(); }",
+            ),
+        ];
+        for (path, content) in files {
+            let path = repo_dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        for file_collection in [FileCollection::Walk, FileCollection::ModuleTree] {
+            let analyze = |crate_path| {
+                let analyzed = analyze_crate(
+                    &workspace,
+                    file_collection,
+                    crate_path,
+                    &CrateAnalyzis::default(),
+                )
+                .unwrap();
+                analyzed.macro_usage.unwrap()
+            };
+            let root = analyze("repo");
+            assert!(root.derive_macro_usage.derives.0.is_empty());
+            assert!(root.macro_invocations.0.is_empty());
+            let member = analyze("repo/member");
+            assert_eq!(member.derive_macro_usage.derives.0["Clone"], 1);
+            assert_eq!(member.macro_invocations.0["println"], 1);
+        }
+    }
+
+    #[test]
+    fn counts_conditional_attributes_by_predicate() {
+        let analyzis = analyze(
+            r#"
+            #![cfg_attr(docsrs, feature(doc_cfg))]
+
+            #[cfg_attr(
+                all(a,   b),
+                derive(Debug, serde::Serialize),
+                cfg_attr(feature = "x", derive(Clone), tracing::instrument)
+            )]
+            struct Nested;
+
+            #[cfg_attr(test, derive(Default))]
+            #[cfg_attr(test, derive(Default, Hash))]
+            struct Repeated;
+            "#,
+        );
+        let conditional = &analyzis.conditional_attributes.0;
+        let mut predicates: Vec<&str> = conditional.keys().map(String::as_str).collect();
+        predicates.sort();
+        assert_eq!(
+            predicates,
+            [
+                "all(a, b)",
+                "all(all(a, b), feature = \"x\")",
+                "docsrs",
+                "test"
+            ]
+        );
+
+        assert_eq!(
+            names(&conditional["docsrs"].inner_attribute_invocations),
+            ["feature"]
+        );
+        assert_eq!(
+            names(&conditional["all(a, b)"].derives),
+            ["Debug", "serde::Serialize"]
+        );
+        let nested = &conditional["all(all(a, b), feature = \"x\")"];
+        assert_eq!(names(&nested.derives), ["Clone"]);
+        assert_eq!(
+            names(&nested.attribute_macro_invocations),
+            ["tracing::instrument"]
+        );
+        assert_eq!(conditional["test"].derives.0["Default"], 2);
+        assert_eq!(conditional["test"].derives.0["Hash"], 1);
+        assert_eq!(
+            conditional["test"].builtin_attribute_macro_invocations.0["derive"],
+            2
+        );
+        // Conditional derives are counted apart
+        assert!(analyzis.derive_macro_usage.derives.0.is_empty());
+    }
+
+    #[test]
+    fn counts_helpers_of_derived_items_only() {
+        let analyzis = analyze(
+            r#"
+            #[derive(Debug, thiserror::Error)]
+            /// Docs between the attributes
+            #[error("failed")]
+            struct Failed;
+
+            #[error("not derived")]
+            struct NotDerived;
+
+            #[builder]
+            fn build() {}
+
+            #[cfg_attr(feature = "serde", derive(Serialize), serde(rename_all = "camelCase"))]
+            struct Conditional {
+                #[serde(default)]
+                field: u8,
+            }
+
+            #[derive(Clone)]
+            enum Derived {
+                #[default]
+                A,
+                B {
+                    #[skip]
+                    field: u8,
+                },
+            }
+
+            #[pin_project]
+            struct Projected {
+                #[pin]
+                field: u8,
+            }
+            "#,
+        );
+        assert_eq!(
+            names(&analyzis.derive_helper_attributes),
+            ["default", "error", "pin", "serde", "skip"]
+        );
+        assert_eq!(analyzis.derive_helper_attributes.0["error"], 1);
+        assert_eq!(
+            names(&analyzis.attribute_macro_invocations),
+            ["builder", "error", "pin_project"]
+        );
+        let conditional = &analyzis.conditional_attributes.0["feature = \"serde\""];
+        assert_eq!(names(&conditional.derive_helper_attributes), ["serde"]);
+    }
+}
@@ -1,56 +1,80 @@
-use std::fmt;
+use std::{collections::BTreeMap, path::PathBuf};
 
-use serde::{de::{self, MapAccess, Visitor}, Deserialize, Deserializer};
+use serde::Deserialize;
 
+/// `[lib]`, `[[bin]]`, `[[example]]`, `[[test]]` or `[[bench]]` section
 #[derive(Deserialize, Default, Debug)]
-pub struct CargoTomlBinEntry {
+#[serde(rename_all = "kebab-case", default)]
+pub struct CargoTomlTarget {
+    pub name: Option<String>,
+    pub path: Option<String>,
+    #[serde(alias = "proc_macro")]
+    pub proc_macro: bool,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct CargoTomlPackage {
+    #[serde(default)]
     pub name: String,
+    /// Either the path of the build script or `false`
+    pub build: Option<toml::Value>,
+    pub autobins: Option<bool>,
+    pub autoexamples: Option<bool>,
+    pub autotests: Option<bool>,
+    pub autobenches: Option<bool>,
+}
+
+/// Dependency tables of a manifest or of one of its `[target.<cfg>]` sections. Each
+/// dependency is either a version or a table, possibly renaming the `package`
+#[derive(Deserialize, Default, Debug)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct CargoTomlDependencies {
+    pub dependencies: BTreeMap<String, toml::Value>,
+    pub dev_dependencies: BTreeMap<String, toml::Value>,
+    pub build_dependencies: BTreeMap<String, toml::Value>,
 }
 
 #[derive(Deserialize, Default, Debug)]
+#[serde(rename_all = "kebab-case", default)]
 pub struct CargoTomlWorkspace {
     pub members: Vec<String>,
+    pub exclude: Vec<String>,
+    pub default_members: Vec<String>,
 }
 
-// #[derive(Deserialize, Default, Debug)]
-// pub struct CargoTomlLib;
-
-#[derive(Debug, Default)]
-pub struct LibExists;
-
-struct LibExistsVisitor;
-
-impl<'de> Visitor<'de> for LibExistsVisitor {
-    type Value = LibExists;
-
-    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str("a [lib] section")
-    }
+#[derive(Deserialize, Default, Debug)]
+pub struct CargoToml {
+    pub package: Option<CargoTomlPackage>,
+    pub workspace: Option<CargoTomlWorkspace>,
+    pub lib: Option<CargoTomlTarget>,
+    pub bin: Option<Vec<CargoTomlTarget>>,
+    pub example: Option<Vec<CargoTomlTarget>>,
+    pub test: Option<Vec<CargoTomlTarget>>,
+    pub bench: Option<Vec<CargoTomlTarget>>,
+    #[serde(flatten)]
+    pub dependencies: CargoTomlDependencies,
+    pub target: Option<BTreeMap<String, CargoTomlDependencies>>,
+}
 
-    fn visit_map<V>(self, mut _map: V) -> Result<LibExists, V::Error>
-    where
-        V: MapAccess<'de>,
-    {
-        // We simply consume the map without processing any keys/values.
-        while let Some(_key) = _map.next_key::<String>()? {
-            let _ignored: de::IgnoredAny = _map.next_value()?;
-        }
-        Ok(LibExists)
-    }
+/// Output of `cargo metadata --format-version 1 --no-deps`, listing the workspace members
+#[derive(Deserialize, Debug)]
+pub struct CargoMetadata {
+    pub packages: Vec<CargoMetadataPackage>,
 }
 
-impl<'de> Deserialize<'de> for LibExists {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        deserializer.deserialize_map(LibExistsVisitor)
-    }
+#[derive(Deserialize, Debug)]
+pub struct CargoMetadataPackage {
+    pub name: String,
+    pub version: String,
+    pub edition: String,
+    pub manifest_path: PathBuf,
+    pub targets: Vec<CargoMetadataTarget>,
 }
 
-#[derive(Deserialize, Default, Debug)]
-pub struct CargoToml {
-    pub workspace: Option<CargoTomlWorkspace>,
-    pub lib: Option<LibExists>,
-    pub bin: Option<Vec<CargoTomlBinEntry>>
+#[derive(Deserialize, Debug)]
+pub struct CargoMetadataTarget {
+    pub name: String,
+    pub kind: Vec<String>,
+    pub src_path: PathBuf,
 }
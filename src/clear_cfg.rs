@@ -1,4 +1,3 @@
-use chrono::Local;
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
@@ -11,12 +10,12 @@ use tree_sitter::{Node, Parser};
 
 use crate::{
     crate_paths::CratePaths,
-    state::ScraperState,
-    utils::{pretty_print, remove_data_prefix},
+    ignore::{collect_rust_files, IgnoreRules},
+    state::{ScraperState, Stage},
+    utils::pretty_print,
+    workspace::Workspace,
 };
 
-const RANGES_TO_REMOVE_PATH: &str = "./data/cfg_ranges.ron";
-
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
 pub struct Range(usize, usize);
 
@@ -29,7 +28,7 @@ impl From<tree_sitter::Range> for Range {
 #[derive(Serialize, Deserialize, Default, Debug)]
 pub struct RangesToRemove(pub HashMap<String, Vec<Range>>);
 
-impl_save_load!(RangesToRemove, RANGES_TO_REMOVE_PATH);
+impl_save_load!(RangesToRemove, Workspace::ranges_to_remove_path);
 
 fn validate_cfg(token_tree: Node, bytes: &[u8]) -> bool {
     // Checking for the format: "(not(feature = "feat"))"
@@ -133,39 +132,32 @@ pub fn get_file_cfg_ranges(bytes: &[u8]) -> Result<Vec<Range>, Box<dyn Error>> {
 }
 
 fn get_cfg_ranges(
+    workspace: &Workspace,
     path: &Path,
+    rules: &IgnoreRules,
     ranges_to_remove: &mut RangesToRemove,
 ) -> Result<(), Box<dyn Error>> {
-    for entry in fs::read_dir(path)? {
-        let entry = entry.unwrap();
-        let path = entry.path();
-
-        if path.is_dir() {
-            get_cfg_ranges(&path, ranges_to_remove)?;
-            continue;
-        }
-
-        let file_name = path.file_name();
-        if let Some(file_name) = file_name {
-            if file_name != ".macro-expanded.rs"
-                && path.extension() == Some(std::ffi::OsStr::new("rs"))
-            {
-                let string = fs::read_to_string(&path)?;
-                let bytes = string.as_bytes();
-                let ranges = get_file_cfg_ranges(bytes)?;
-                let parsed_path = remove_data_prefix(path.to_str().unwrap());
-                ranges_to_remove.0.insert(parsed_path, ranges);
-            }
-        }
+    let mut files = Vec::new();
+    collect_rust_files(path, rules, &mut files, &mut Vec::new())?;
+    for path in files {
+        let string = fs::read_to_string(&path)?;
+        let bytes = string.as_bytes();
+        let ranges = get_file_cfg_ranges(bytes)?;
+        let parsed_path = workspace.relative_repo_path(&path);
+        ranges_to_remove.0.insert(parsed_path, ranges);
     }
     Ok(())
 }
 
-fn get_crates_cfg_ranges(crate_paths: &CratePaths) -> Result<RangesToRemove, Box<dyn Error>> {
-    let mut ranges_to_remove = RangesToRemove::load().unwrap_or_default();
+fn get_crates_cfg_ranges(
+    workspace: &Workspace,
+    crate_paths: &CratePaths,
+) -> Result<RangesToRemove, Box<dyn Error>> {
+    let mut ranges_to_remove = RangesToRemove::load(workspace).unwrap_or_default();
     for crate_path in crate_paths {
-        let crate_path = Path::new("./data/repos").join(crate_path);
-        match get_cfg_ranges(&crate_path, &mut ranges_to_remove) {
+        let rules = IgnoreRules::for_crate(workspace, crate_path);
+        let crate_path = workspace.crate_dir(crate_path);
+        match get_cfg_ranges(workspace, &crate_path, &rules, &mut ranges_to_remove) {
             Ok(_) => {}
             Err(e) => {
                 println!("Failed to parse {:?}: {}", crate_path, e);
@@ -173,7 +165,7 @@ fn get_crates_cfg_ranges(crate_paths: &CratePaths) -> Result<RangesToRemove, Box
         }
     }
 
-    ranges_to_remove.save()?;
+    ranges_to_remove.save(workspace)?;
     Ok(ranges_to_remove)
 }
 
@@ -201,8 +193,10 @@ fn remove_file_ranges(file_path: &Path, ranges: &[Range]) -> io::Result<()> {
 }
 
 fn process_directory(
+    workspace: &Workspace,
     source: &Path,
     dest: &Path,
+    rules: &IgnoreRules,
     ranges_to_remove: &RangesToRemove,
 ) -> io::Result<()> {
     // Create the destination directory if it doesn't exist
@@ -213,36 +207,52 @@ fn process_directory(
         let entry = entry?;
         let src_path = entry.path();
         let dest_path = dest.join(src_path.file_name().unwrap());
+        if rules.is_ignored(&src_path) {
+            continue;
+        }
 
         // Check if the item is a file or a directory
         if src_path.is_file() {
             fs::copy(&src_path, &dest_path)?;
             // Copy the file to the destination directory
             if src_path.extension() == Some(std::ffi::OsStr::new("rs")) {
-                let parsed_path = remove_data_prefix(src_path.to_str().unwrap());
+                let parsed_path = workspace.relative_repo_path(&src_path);
                 if let Some(ranges) = ranges_to_remove.0.get(&parsed_path) {
                     remove_file_ranges(&dest_path, ranges)?;
                 }
             }
         } else if src_path.is_dir() {
             // Recursively copy directories
-            process_directory(&src_path, &dest_path, ranges_to_remove)?;
+            process_directory(workspace, &src_path, &dest_path, rules, ranges_to_remove)?;
         }
     }
 
     Ok(())
 }
 
-fn parse_repositories(ranges_to_remove: &RangesToRemove) -> Result<(), Box<dyn Error>> {
-    let source_dir = Path::new("data/repos");
-    let dest_dir = Path::new("data/parsed_repos");
+fn parse_repositories(
+    workspace: &Workspace,
+    ranges_to_remove: &RangesToRemove,
+) -> Result<(), Box<dyn Error>> {
+    let source_dir = workspace.repos_path();
+    let dest_dir = workspace.parsed_repos_path();
 
-    process_directory(source_dir, dest_dir, ranges_to_remove)?;
+    // Each repository is copied with its own ignore rules
+    for entry in fs::read_dir(&source_dir)? {
+        let repo_dir = entry?.path();
+        if !repo_dir.is_dir() {
+            continue;
+        }
+        let rules = IgnoreRules::for_repo(workspace, &repo_dir);
+        let dest_path = dest_dir.join(repo_dir.file_name().unwrap());
+        process_directory(workspace, &repo_dir, &dest_path, &rules, ranges_to_remove)?;
+    }
 
     Ok(())
 }
 
 pub fn parse_code(
+    workspace: &Workspace,
     state: &mut ScraperState,
     crate_paths: &CratePaths,
 ) -> Result<(), Box<dyn Error>> {
@@ -254,11 +264,11 @@ pub fn parse_code(
         return Ok(());
     }
 
-    let ranges = get_crates_cfg_ranges(crate_paths)?;
-    parse_repositories(&ranges)?;
+    let ranges = get_crates_cfg_ranges(workspace, crate_paths)?;
+    parse_repositories(workspace, &ranges)?;
 
     pretty_print("Copied repos while clearing conditional compilation", None);
-    state.cleared_cfg_at = Some(Local::now());
-    state.save()?;
+    state.complete(workspace, Stage::ClearCfg);
+    state.save(workspace)?;
     Ok(())
 }
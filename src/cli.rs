@@ -0,0 +1,240 @@
+use std::{error::Error, fs, path::PathBuf};
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::{
+    crate_paths::DiscoveryBackend, git::CloneStrategy, github::RepoSelection,
+    module_tree::FileCollection, state::Stage, workspace::DEFAULT_DATA_PATH,
+};
+
+/// `--source` value cloning from the GitHub URLs
+pub const GITHUB_SOURCE: &str = "github";
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Analyzes macro usage in popular Rust repositories")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Directory where every artifact of the dataset is stored
+    #[arg(long, global = true, default_value = DEFAULT_DATA_PATH)]
+    pub data_dir: PathBuf,
+
+    #[command(flatten)]
+    pub selection: SelectionArgs,
+
+    /// Run the stage again, invalidating every stage that depends on it. Can be repeated
+    #[arg(long, global = true, value_enum)]
+    pub force: Vec<Stage>,
+
+    /// How repositories are cloned. Recorded in the state and reused on later runs,
+    /// changing it clones the repositories again
+    #[arg(long, global = true, value_enum)]
+    pub clone_strategy: Option<CloneStrategy>,
+
+    /// Clone from a directory of bare mirrors (`<owner>.<name>.git`), checkouts or
+    /// `.tar.gz`/`.crate` archives, or from a remote such as `file:///srv/mirrors`.
+    /// Recorded in the state and reused on later runs. Pass `github` to clone from GitHub again
+    #[arg(long, global = true)]
+    pub source: Option<String>,
+
+    /// How the crates of each repository are found. Recorded in the state and reused
+    /// on later runs, changing it finds the crates again
+    #[arg(long, global = true, value_enum)]
+    pub discovery: Option<DiscoveryBackend>,
+
+    /// How the source files of each crate are collected. Recorded in the state and reused
+    /// on later runs, changing it analyzes and counts the crates again
+    #[arg(long, global = true, value_enum)]
+    pub file_collection: Option<FileCollection>,
+
+    /// GitHub GraphQL endpoint, e.g. the one started by `mock-github`
+    #[arg(long, global = true)]
+    pub graphql_url: Option<String>,
+}
+
+/// Options changing which repositories are fetched. Omitted options are kept from the
+/// selection recorded in the state, and any change invalidates the fetched repositories
+#[derive(Args, Debug)]
+pub struct SelectionArgs {
+    /// Number of most popular repositories to fetch
+    #[arg(long, global = true)]
+    pub repos: Option<usize>,
+
+    /// Minimum number of stars
+    #[arg(long, global = true)]
+    pub min_stars: Option<i64>,
+
+    /// Only fetch repositories with this topic. Can be repeated
+    #[arg(long, global = true)]
+    pub topic: Vec<String>,
+
+    /// Only fetch repositories of this user or organization. Can be repeated
+    #[arg(long, global = true)]
+    pub org: Vec<String>,
+
+    /// Creation date or range, e.g. `>=2020-01-01` or `2020-01-01..2021-01-01`
+    #[arg(long, global = true)]
+    pub created: Option<String>,
+
+    /// Last push date or range, e.g. `>=2024-01-01`
+    #[arg(long, global = true)]
+    pub pushed: Option<String>,
+
+    /// License keyword, e.g. `mit` or `apache-2.0`
+    #[arg(long, global = true)]
+    pub license: Option<String>,
+
+    /// Fetch exactly this `owner/name` repository. Can be repeated
+    #[arg(long, global = true)]
+    pub repo: Vec<String>,
+
+    /// File with one `owner/name` repository per line to fetch
+    #[arg(long, global = true)]
+    pub repo_list: Option<PathBuf>,
+
+    /// Start from the default selection instead of the recorded one
+    #[arg(long, global = true)]
+    pub reset_selection: bool,
+}
+
+impl SelectionArgs {
+    pub fn apply(self, selection: &RepoSelection) -> Result<RepoSelection, Box<dyn Error>> {
+        let mut selection = match self.reset_selection {
+            true => RepoSelection::default(),
+            false => selection.clone(),
+        };
+        if let Some(count) = self.repos {
+            selection.count = count;
+        }
+        if let Some(min_stars) = self.min_stars {
+            selection.min_stars = min_stars;
+        }
+        if !self.topic.is_empty() {
+            selection.topics = self.topic;
+        }
+        if !self.org.is_empty() {
+            selection.orgs = self.org;
+        }
+        if self.created.is_some() {
+            selection.created = self.created;
+        }
+        if self.pushed.is_some() {
+            selection.pushed = self.pushed;
+        }
+        if self.license.is_some() {
+            selection.license = self.license;
+        }
+        let mut repos = self.repo;
+        if let Some(repo_list) = self.repo_list {
+            let content = fs::read_to_string(repo_list)?;
+            repos.extend(
+                content
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(str::to_string),
+            );
+        }
+        if !repos.is_empty() {
+            selection.repos = repos;
+        }
+        Ok(selection)
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    #[command(flatten)]
+    Pipeline(PipelineCommand),
+    /// Stand in for the GitHub API, answering each request with the next fixture of
+    /// the directory. Point `--graphql-url` at it to run `fetch` offline
+    MockGithub {
+        /// Directory with one `*.json` canned response per request, served in file name order
+        fixtures: PathBuf,
+        #[arg(long, default_value_t = 8081)]
+        port: u16,
+    },
+}
+
+impl Default for Command {
+    fn default() -> Self {
+        Self::Pipeline(PipelineCommand::default())
+    }
+}
+
+/// Commands working on the dataset of `--data-dir`
+#[derive(Subcommand, Debug, Clone)]
+pub enum PipelineCommand {
+    /// Run every stage up to `export` and start the HTTP server (default)
+    Run {
+        /// Also clear conditional compilation, expand macros and count the expanded code
+        #[arg(long)]
+        with_expanded: bool,
+    },
+    /// Fetch the most popular repositories from GitHub
+    Fetch,
+    /// Clone the fetched repositories
+    Clone,
+    /// Clone the repositories of a manifest at the commits it records, replacing the
+    /// fetched and cloned ones. Every clone writes `manifest.ron` to the data directory
+    Restore {
+        /// Manifest written by a previous clone, e.g. `data/manifest.ron`
+        manifest: PathBuf,
+    },
+    /// Find the crates inside the cloned repositories
+    Discover,
+    /// Count macro definitions and invocations of every crate
+    Analyze,
+    /// Count characters and lines of every crate
+    Count,
+    /// Copy the repositories while removing conditionally compiled code
+    ClearCfg,
+    /// Expand the macros of every crate with `cargo expand`
+    Expand,
+    /// Count characters and lines of the expanded crates
+    CountExpanded,
+    /// Write the aggregated data to `data/data.json`
+    Export,
+    /// Serve the exported data over HTTP
+    Serve,
+    /// Analyze local directories in place instead of the GitHub repositories.
+    /// Refuses to run in a `--data-dir` holding fetched repositories
+    Local {
+        /// Directories to analyze, each one counted as a repository
+        #[arg(required = true)]
+        dirs: Vec<PathBuf>,
+        /// Start the HTTP server after exporting the data
+        #[arg(long)]
+        serve: bool,
+    },
+    /// Analyze published crates instead of the GitHub repositories, unpacking them into
+    /// `<name>.<version>` folders. Use a separate `--data-dir` to keep the GitHub dataset intact
+    Crates {
+        /// Local snapshot of the crates.io index, used to find the name and version of each file
+        #[arg(long)]
+        index: PathBuf,
+        /// Directory of downloaded `<name>-<version>.crate` files. Only the highest
+        /// non-yanked version of each crate is analyzed
+        #[arg(long)]
+        crates_dir: PathBuf,
+        /// `crates.csv` of the crates.io database dump, ranking the crates by downloads.
+        /// Recent dumps read them from the `crate_downloads.csv` next to it
+        #[arg(long)]
+        downloads: Option<PathBuf>,
+        /// Number of most downloaded crates to analyze. Every crate by default
+        #[arg(long)]
+        count: Option<usize>,
+        /// Start the HTTP server after exporting the data
+        #[arg(long)]
+        serve: bool,
+    },
+}
+
+impl Default for PipelineCommand {
+    fn default() -> Self {
+        Self::Run {
+            with_expanded: false,
+        }
+    }
+}
@@ -1,12 +1,22 @@
-use chrono::Local;
-use std::{error::Error, fs, path::Path};
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
 use tree_sitter::{Node, Parser};
 
 use crate::{
     crate_paths::get_repo_path,
-    results::{AnalyzisResults, CharLineCount},
-    state::ScraperState,
+    ignore::{collect_rust_files, IgnoreRules},
+    module_tree::{collect_module_tree, FileCollection},
+    results::{
+        merge_source_count, AnalyzisResults, CharLineCount, ClassSourceCount, CrateAnalyzis,
+    },
+    source_class::classify_file,
+    state::{ScraperState, Stage},
+    targets::crate_targets,
     utils::pretty_print,
+    workspace::Workspace,
 };
 
 fn count_chars(root: Node, bytes: &[u8]) -> usize {
@@ -50,33 +60,46 @@ pub fn count_file_code(bytes: &[u8]) -> Result<CharLineCount, Box<dyn Error>> {
     }
 }
 
-fn count_dir_code(path: &Path) -> Result<CharLineCount, Box<dyn Error>> {
-    let mut count = CharLineCount(0, 0);
-    for entry in fs::read_dir(path)? {
-        let entry = entry.unwrap();
-        let path = entry.path();
+// Counts the files by source class, relative to the repository directory
+fn count_files_code(
+    files: &[PathBuf],
+    repo_dir: &Path,
+) -> Result<ClassSourceCount, Box<dyn Error>> {
+    let mut count = ClassSourceCount::new();
+    for file in files {
+        let string = fs::read_to_string(file)?;
+        let class = classify_file(file.strip_prefix(repo_dir).unwrap_or(file), &string);
+        *count.entry(class).or_default() += count_file_code(string.as_bytes())?;
+    }
+    Ok(count)
+}
 
-        if path.is_dir() {
-            count += count_dir_code(&path)?;
-            continue;
+fn collect_crate_files(
+    crate_dir: &Path,
+    crate_analyzis: &CrateAnalyzis,
+    rules: &IgnoreRules,
+    collection: FileCollection,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    match collection {
+        FileCollection::Walk => {
+            let mut files = Vec::new();
+            collect_rust_files(crate_dir, rules, &mut files, &mut Vec::new())?;
+            Ok(files)
         }
-
-        let file_name = path.file_name();
-        if let Some(file_name) = file_name {
-            if file_name != ".macro-expanded.rs"
-                && path.extension() == Some(std::ffi::OsStr::new("rs"))
-            {
-                let string = fs::read_to_string(&path)?;
-                let bytes = string.as_bytes();
-                let c = count_file_code(bytes)?;
-                count += c;
-            }
+        FileCollection::ModuleTree => {
+            let targets = crate_targets(crate_dir, crate_analyzis.package.as_ref());
+            let module_tree = collect_module_tree(crate_dir, &targets, rules);
+            Ok(module_tree
+                .files
+                .into_iter()
+                .map(|(path, _)| path)
+                .collect())
         }
     }
-    Ok(count)
 }
 
 pub fn count_crates_code(
+    workspace: &Workspace,
     state: &mut ScraperState,
     results: &mut AnalyzisResults,
 ) -> Result<(), Box<dyn Error>> {
@@ -87,19 +110,33 @@ pub fn count_crates_code(
         );
         return Ok(());
     }
-    for crate_path in results.crates.clone().keys() {
-        match count_dir_code(&Path::new("./data/repos").join(crate_path)) {
-            Ok(c) => {
-                results.update_crate(crate_path, &mut |crate_analyzis| {
+    results.clear_source_count();
+
+    for (crate_path, crate_analyzis) in results.crates.clone() {
+        let crate_dir = workspace.crate_dir(&crate_path);
+        let repo_path = get_repo_path(&crate_path);
+        let rules = IgnoreRules::for_crate(workspace, &crate_path);
+        let count = collect_crate_files(&crate_dir, &crate_analyzis, &rules, state.file_collection)
+            .and_then(|files| count_files_code(&files, &workspace.repo_dir(&repo_path)));
+        match count {
+            Ok(class_count) => {
+                let c = class_count
+                    .values()
+                    .fold(CharLineCount(0, 0), |acc, count| acc + *count);
+                results.update_crate(&crate_path, &mut |crate_analyzis| {
                     crate_analyzis.source_count = Some(c);
+                    crate_analyzis.class_source_count = Some(class_count.clone());
                 });
-                let repo_path = get_repo_path(crate_path);
                 results.update_repo(&repo_path, &mut |repo_analyzis| {
                     if let Some(count) = repo_analyzis.source_count {
                         repo_analyzis.source_count = Some(count + c);
                     } else {
                         repo_analyzis.source_count = Some(c);
                     }
+                    let mut repo_class_count =
+                        repo_analyzis.class_source_count.take().unwrap_or_default();
+                    merge_source_count(&mut repo_class_count, &class_count);
+                    repo_analyzis.class_source_count = Some(repo_class_count);
                 })
             }
             Err(e) => {
@@ -109,13 +146,14 @@ pub fn count_crates_code(
     }
 
     pretty_print("Characters and lines counted", None);
-    state.counted_code_at = Some(Local::now());
-    state.save()?;
-    results.save()?;
+    state.complete(workspace, Stage::Count);
+    state.save(workspace)?;
+    results.save(workspace)?;
     Ok(())
 }
 
 pub fn count_expanded_code(
+    workspace: &Workspace,
     state: &mut ScraperState,
     results: &mut AnalyzisResults,
 ) -> Result<(), Box<dyn Error>> {
@@ -126,10 +164,12 @@ pub fn count_expanded_code(
         );
         return Ok(());
     }
+    results.clear_expanded_ok_count();
 
     for crate_path in results.crates.clone().keys() {
         let repo_path = get_repo_path(crate_path);
-        let expanded_path = Path::new("./data/parsed_repos")
+        let expanded_path = workspace
+            .parsed_repos_path()
             .join(crate_path)
             .join(".macro-expanded.rs");
         let count = match fs::read_to_string(&expanded_path) {
@@ -160,8 +200,8 @@ pub fn count_expanded_code(
     }
 
     pretty_print("Expanded characters and lines counted", None);
-    state.counted_expanded_chars_at = Some(Local::now());
-    results.save()?;
-    state.save()?;
+    state.complete(workspace, Stage::CountExpanded);
+    results.save(workspace)?;
+    state.save(workspace)?;
     Ok(())
 }
@@ -1,13 +1,20 @@
-use chrono::Local;
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
-use std::{error::Error, fs};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use ts_rs::TS;
 
 use crate::{
-    cargo::CargoToml,
-    state::ScraperState,
-    utils::{pretty_print, remove_data_prefix},
+    cargo::{CargoMetadata, CargoToml},
+    state::{ScraperState, Stage},
+    utils::pretty_print,
+    workspace::Workspace,
 };
-const CRATE_PATHS_PATH: &str = "./data/crates.ron";
 
 #[derive(Serialize, Deserialize, Default)]
 pub struct CratePaths(pub Vec<String>);
@@ -31,7 +38,41 @@ impl<'a> IntoIterator for &'a CratePaths {
         self.0.iter()
     }
 }
-impl_save_load!(CratePaths, CRATE_PATHS_PATH);
+impl_save_load!(CratePaths, Workspace::crate_paths_path);
+
+/// How the crates of a repository are found
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DiscoveryBackend {
+    /// Follow the workspace members of the `Cargo.toml` files
+    #[default]
+    Toml,
+    /// Ask `cargo metadata`, falling back to the `Cargo.toml` files when cargo fails
+    CargoMetadata,
+}
+
+/// Package of a crate, as reported by `cargo metadata`
+#[derive(TS, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CratePackage {
+    pub name: String,
+    pub version: String,
+    pub edition: String,
+    pub targets: Vec<CrateTarget>,
+}
+
+#[derive(TS, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CrateTarget {
+    pub name: String,
+    /// e.g. `lib`, `bin`, `proc-macro`, `example`, `test`, `bench` or `custom-build`
+    pub kinds: Vec<String>,
+    /// Root source file, relative to the crate directory
+    pub src_path: String,
+}
+
+/// Packages of the crates found with `cargo metadata`, by crate path
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct CratePackages(pub BTreeMap<String, CratePackage>);
+
+impl_save_load!(CratePackages, Workspace::crate_packages_path);
 
 pub fn get_repo_path(crate_path: &str) -> String {
     let path_parts: Vec<&str> = crate_path.split('/').collect();
@@ -39,7 +80,14 @@ pub fn get_repo_path(crate_path: &str) -> String {
     repo_path.to_string()
 }
 
-pub fn find_project_crates(root_dir: &std::path::Path) -> CratePaths {
+// Removes the `.` components, so that members can be compared to the exclusions
+fn normalize_path(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| *component != std::path::Component::CurDir)
+        .collect()
+}
+
+pub fn find_project_crates(workspace: &Workspace, root_dir: &std::path::Path) -> CratePaths {
     let mut crate_paths = Vec::new();
 
     // Read the Cargo.toml file at the root directory
@@ -52,7 +100,7 @@ pub fn find_project_crates(root_dir: &std::path::Path) -> CratePaths {
                 let path = entry.path();
 
                 if path.is_dir() {
-                    crate_paths.extend(find_project_crates(&path).0)
+                    crate_paths.extend(find_project_crates(workspace, &path).0)
                 }
             }
             return CratePaths(crate_paths);
@@ -60,69 +108,362 @@ pub fn find_project_crates(root_dir: &std::path::Path) -> CratePaths {
     };
     let cargo_toml: CargoToml = toml::from_str(&cargo_toml).unwrap_or_default();
 
-    // Check if the root directory is a crate or a workspace
-    if cargo_toml.workspace.is_none() {
+    let Some(cargo_workspace) = &cargo_toml.workspace else {
         // If it's a crate, add its path to the CratePaths vector
-        crate_paths.push(remove_data_prefix(&root_dir.to_string_lossy()));
-    } else {
-        // If it's a workspace, read the members field to get the paths to the crates inside that workspace
-        if let Some(workspace) = &cargo_toml.workspace {
-            for member in &workspace.members {
-                if member == "." {
-                    for entry in fs::read_dir(root_dir).unwrap() {
-                        let entry = entry.unwrap();
-                        let path = entry.path();
-
-                        if path.is_dir() {
-                            crate_paths.extend(find_project_crates(&path).0);
-                        }
-                    }
-                } else if member.find('*').is_some() {
-                    if let Ok(glob) = glob::glob(&format!("{}/{}", root_dir.display(), member)) {
-                        for entry in glob {
-                            let entry = entry.unwrap();
-                            if entry.is_dir() {
-                                crate_paths.extend(find_project_crates(entry.as_path()).0);
-                            }
-                        }
-                    }
-                } else {
-                    crate_paths.extend(find_project_crates(&root_dir.join(member)).0);
-                }
+        crate_paths.push(workspace.relative_repo_path(root_dir));
+        return CratePaths(crate_paths);
+    };
+
+    // A workspace manifest may also declare the root crate
+    if cargo_toml.package.is_some() {
+        crate_paths.push(workspace.relative_repo_path(root_dir));
+    }
+
+    // Default members have to be members too, but may be missing from `members`
+    // when they are path dependencies of the root crate
+    let mut member_dirs: Vec<PathBuf> = Vec::new();
+    for member in cargo_workspace
+        .members
+        .iter()
+        .chain(&cargo_workspace.default_members)
+    {
+        // Without `.` components, so that `./b` is recorded as `b`
+        let member_dir = root_dir.join(normalize_path(Path::new(member)));
+        if member.contains(['*', '?', '[']) {
+            if let Ok(glob) = glob::glob(&member_dir.to_string_lossy()) {
+                member_dirs.extend(glob.flatten().filter(|entry| entry.is_dir()));
             }
+        } else {
+            member_dirs.push(member_dir);
         }
     }
 
+    let normalized_root = normalize_path(root_dir);
+    let excluded: Vec<PathBuf> = cargo_workspace
+        .exclude
+        .iter()
+        .map(|exclude| normalize_path(&root_dir.join(exclude)))
+        .collect();
+    let mut visited: Vec<PathBuf> = Vec::new();
+    for member_dir in member_dirs {
+        let normalized = normalize_path(&member_dir);
+        // `.` is the root crate, which was already added if the manifest has a package
+        if normalized == normalized_root
+            || !member_dir.is_dir()
+            || visited.contains(&normalized)
+            || excluded
+                .iter()
+                .any(|excluded| normalized.starts_with(excluded))
+        {
+            continue;
+        }
+        crate_paths.extend(find_project_crates(workspace, &member_dir).0);
+        visited.push(normalized);
+    }
+
     CratePaths(crate_paths)
 }
 
+fn cargo_metadata(manifest_path: &Path) -> Result<CargoMetadata, Box<dyn Error>> {
+    // Run from the current directory rather than the repository's, so a
+    // `rust-toolchain` file of the repository doesn't trigger a toolchain download
+    let output = Command::new("cargo")
+        .arg("metadata")
+        .arg("--format-version")
+        .arg("1")
+        .arg("--no-deps")
+        .arg("--offline")
+        .arg("--manifest-path")
+        .arg(manifest_path)
+        .output()?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr)
+            .trim()
+            .to_string()
+            .into());
+    }
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+/// Finds the crates of every workspace of the repository with `cargo metadata`,
+/// recording their packages. Directories where cargo fails are walked with
+/// `find_project_crates` instead.
+pub fn find_project_packages(
+    workspace: &Workspace,
+    repo_dir: &Path,
+    packages: &mut CratePackages,
+) -> CratePaths {
+    let mut crate_paths = Vec::new();
+    find_dir_packages(workspace, repo_dir, repo_dir, packages, &mut crate_paths);
+    CratePaths(crate_paths)
+}
+
+fn find_dir_packages(
+    workspace: &Workspace,
+    repo_dir: &Path,
+    root_dir: &Path,
+    packages: &mut CratePackages,
+    crate_paths: &mut Vec<String>,
+) {
+    let manifest_path = root_dir.join("Cargo.toml");
+    if !manifest_path.is_file() {
+        for entry in fs::read_dir(root_dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                find_dir_packages(workspace, repo_dir, &path, packages, crate_paths);
+            }
+        }
+        return;
+    }
+
+    let metadata = match cargo_metadata(&manifest_path) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            println!(
+                "cargo metadata failed for {:?}, reading Cargo.toml instead: {}",
+                root_dir, e
+            );
+            for crate_path in find_project_crates(workspace, root_dir).0 {
+                if !crate_paths.contains(&crate_path) {
+                    crate_paths.push(crate_path);
+                }
+            }
+            return;
+        }
+    };
+    // cargo reports canonical paths, while the crate paths are relative to the repositories.
+    // Members may be outside of the workspace directory, but not of the repository
+    let canonical_repo = repo_dir.canonicalize().unwrap_or(repo_dir.to_path_buf());
+    for package in metadata.packages {
+        let Some(package_dir) = package.manifest_path.parent() else {
+            continue;
+        };
+        let Ok(relative_dir) = package_dir.strip_prefix(&canonical_repo) else {
+            println!(
+                "Skipping package {} outside of the repository: {:?}",
+                package.name, package_dir
+            );
+            continue;
+        };
+        let crate_path = workspace.relative_repo_path(&repo_dir.join(relative_dir));
+        if crate_paths.contains(&crate_path) {
+            continue;
+        }
+        let targets = package
+            .targets
+            .into_iter()
+            .map(|target| CrateTarget {
+                src_path: target
+                    .src_path
+                    .strip_prefix(package_dir)
+                    .unwrap_or(&target.src_path)
+                    .to_string_lossy()
+                    .to_string(),
+                name: target.name,
+                kinds: target.kind,
+            })
+            .collect();
+        packages.0.insert(
+            crate_path.clone(),
+            CratePackage {
+                name: package.name,
+                version: package.version,
+                edition: package.edition,
+                targets,
+            },
+        );
+        crate_paths.push(crate_path);
+    }
+
+    // cargo leaves out the workspaces nested in this one, such as `fuzz` ones
+    for nested_dir in nested_workspace_dirs(root_dir) {
+        find_dir_packages(workspace, repo_dir, &nested_dir, packages, crate_paths);
+    }
+}
+
+// Directories below `dir` with a workspace manifest of their own, without the ones
+// nested in them
+fn nested_workspace_dirs(dir: &Path) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return dirs;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !path.is_dir() || name == "target" || name.starts_with('.') {
+            continue;
+        }
+        let is_workspace = fs::read_to_string(path.join("Cargo.toml"))
+            .ok()
+            .and_then(|content| toml::from_str::<CargoToml>(&content).ok())
+            .is_some_and(|cargo_toml| cargo_toml.workspace.is_some());
+        match is_workspace {
+            true => dirs.push(path),
+            false => dirs.extend(nested_workspace_dirs(&path)),
+        }
+    }
+    dirs.sort();
+    dirs
+}
+
 pub fn find_crate_paths(
+    workspace: &Workspace,
     state: &mut ScraperState,
-    root_dir: &std::path::Path,
+    repo_dirs: &[PathBuf],
 ) -> Result<CratePaths, Box<dyn Error>> {
     if state.crates_parsed_at.is_some() {
-        if let Some(data) = CratePaths::load() {
+        if let Some(data) = CratePaths::load(workspace) {
             pretty_print("Crates already parsed at", Some(&data.0.len()));
             return Ok(data);
         }
     }
-    let mut all_crate_paths = CratePaths(Vec::new());
-
-    // Iterate over each subdirectory in the root directory
-    for entry in fs::read_dir(root_dir).unwrap() {
-        let entry = entry.unwrap();
-        let path = entry.path();
+    find_repos_crate_paths(workspace, state, repo_dirs)
+}
 
-        // Check if the entry is a directory
-        if path.is_dir() {
-            // Call find_crate_paths on the subdirectory
-            let crate_paths = find_project_crates(&path);
-            all_crate_paths.0.extend(crate_paths.0);
+/// Finds the crates of the given repositories only, such as the local repositories
+/// registered in the workspace. They are always searched again, since their
+/// contents may have changed.
+pub fn find_repos_crate_paths<'a>(
+    workspace: &Workspace,
+    state: &mut ScraperState,
+    repo_dirs: impl IntoIterator<Item = &'a PathBuf>,
+) -> Result<CratePaths, Box<dyn Error>> {
+    let backend = state.discovery;
+    pretty_print("Discovering crates with", Some(&backend));
+    let mut all_crate_paths = CratePaths(Vec::new());
+    let mut packages = CratePackages::default();
+    for repo_dir in repo_dirs {
+        if !repo_dir.is_dir() {
+            continue;
         }
+        let crate_paths = match backend {
+            DiscoveryBackend::Toml => find_project_crates(workspace, repo_dir),
+            DiscoveryBackend::CargoMetadata => {
+                find_project_packages(workspace, repo_dir, &mut packages)
+            }
+        };
+        all_crate_paths.0.extend(crate_paths.0);
     }
 
-    all_crate_paths.save()?;
-    state.crates_parsed_at = Some(Local::now());
+    all_crate_paths.save(workspace)?;
+    packages.save(workspace)?;
+    state.complete(workspace, Stage::Discover);
     pretty_print("Crates found", Some(&all_crate_paths.0.len()));
     Ok(all_crate_paths)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Repository `repo` of a workspace in a temporary directory
+    fn repository(files: &[(&str, &str)]) -> (tempfile::TempDir, Workspace, PathBuf) {
+        let root = tempfile::tempdir().unwrap();
+        let workspace = Workspace::new(root.path());
+        let repo_dir = workspace.repo_dir("repo");
+        for (path, content) in files {
+            let path = repo_dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        (root, workspace, repo_dir)
+    }
+
+    fn package(name: &str) -> String {
+        format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\n", name)
+    }
+
+    #[test]
+    fn finds_packages_of_nested_workspaces_and_outer_members() {
+        let (_root, workspace, repo_dir) = repository(&[
+            (
+                "rust/Cargo.toml",
+                "[workspace]\nmembers = [\"a\", \"../shared\"]\n",
+            ),
+            ("rust/a/Cargo.toml", &package("a")),
+            ("rust/a/src/lib.rs", ""),
+            (
+                "shared/Cargo.toml",
+                &format!("{}workspace = \"../rust\"\n", package("shared")),
+            ),
+            ("shared/src/lib.rs", ""),
+            (
+                "rust/a/fuzz/Cargo.toml",
+                &format!("{}\n[workspace]\n", package("fuzz")),
+            ),
+            ("rust/a/fuzz/src/main.rs", "fn main() {}"),
+        ]);
+        let mut packages = CratePackages::default();
+        let mut crate_paths = find_project_packages(&workspace, &repo_dir, &mut packages).0;
+        crate_paths.sort();
+
+        assert_eq!(
+            crate_paths,
+            ["repo/rust/a", "repo/rust/a/fuzz", "repo/shared"]
+        );
+        assert_eq!(packages.0["repo/rust/a/fuzz"].name, "fuzz");
+    }
+
+    fn crate_paths(files: &[(&str, &str)]) -> Vec<String> {
+        let (_root, workspace, repo_dir) = repository(files);
+        let mut crate_paths = find_project_crates(&workspace, &repo_dir).0;
+        crate_paths.sort();
+        crate_paths
+    }
+
+    #[test]
+    fn finds_a_single_package() {
+        let crate_paths = crate_paths(&[("Cargo.toml", &package("a"))]);
+        assert_eq!(crate_paths, ["repo"]);
+    }
+
+    #[test]
+    fn finds_the_root_package_of_a_workspace_once() {
+        let manifest = format!("{}[workspace]\nmembers = [\".\", \"./b\"]\n", package("a"));
+        let crate_paths =
+            crate_paths(&[("Cargo.toml", &manifest), ("b/Cargo.toml", &package("b"))]);
+        assert_eq!(crate_paths, ["repo", "repo/b"]);
+    }
+
+    #[test]
+    fn finds_members_of_a_virtual_workspace() {
+        let crate_paths = crate_paths(&[
+            (
+                "Cargo.toml",
+                "[workspace]\nmembers = [\"crates/*\", \"tool\"]\n",
+            ),
+            ("crates/a/Cargo.toml", &package("a")),
+            ("crates/b/Cargo.toml", &package("b")),
+            ("crates/README.md", ""),
+            ("tool/Cargo.toml", &package("tool")),
+        ]);
+        assert_eq!(crate_paths, ["repo/crates/a", "repo/crates/b", "repo/tool"]);
+    }
+
+    #[test]
+    fn finds_default_members_missing_from_members() {
+        let manifest = format!(
+            "{}[workspace]\nmembers = [\"a\"]\ndefault-members = [\".\", \"b\"]\n",
+            package("root")
+        );
+        let crate_paths = crate_paths(&[
+            ("Cargo.toml", &manifest),
+            ("a/Cargo.toml", &package("a")),
+            ("b/Cargo.toml", &package("b")),
+        ]);
+        assert_eq!(crate_paths, ["repo", "repo/a", "repo/b"]);
+    }
+
+    #[test]
+    fn skips_excluded_members() {
+        let manifest = "[workspace]\nmembers = [\"crates/*\", \"./examples/a\"]\nexclude = [\"./crates/old\", \"examples\"]\n";
+        let crate_paths = crate_paths(&[
+            ("Cargo.toml", manifest),
+            ("crates/new/Cargo.toml", &package("new")),
+            ("crates/old/Cargo.toml", &package("old")),
+            ("examples/a/Cargo.toml", &package("example")),
+        ]);
+        assert_eq!(crate_paths, ["repo/crates/new"]);
+    }
+}
@@ -0,0 +1,290 @@
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    source::unpack_archive,
+    state::{ScraperState, Stage},
+    utils::pretty_print,
+    workspace::Workspace,
+};
+
+/// Published crate version analyzed instead of a GitHub repository
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PublishedCrate {
+    pub name: String,
+    pub version: String,
+    pub downloads: u64,
+    pub archive: PathBuf,
+}
+
+impl PublishedCrate {
+    /// `<name>.<version>` folder the crate is unpacked into
+    pub fn repo_path(&self) -> String {
+        format!("{}.{}", self.name, self.version)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct PublishedCrates(pub Vec<PublishedCrate>);
+
+impl_save_load!(PublishedCrates, Workspace::published_crates_path);
+
+// Line of a crate file in the crates.io index, one per published version
+#[derive(Deserialize)]
+struct IndexEntry {
+    vers: String,
+    #[serde(default)]
+    yanked: bool,
+}
+
+// Path of the crate file in the index, e.g. `se/rd/serde` or `3/s/syn`
+fn index_file(index_dir: &Path, name: &str) -> PathBuf {
+    let name = name.to_lowercase();
+    match name.len() {
+        1 => index_dir.join("1").join(&name),
+        2 => index_dir.join("2").join(&name),
+        3 => index_dir.join("3").join(&name[..1]).join(&name),
+        _ => index_dir.join(&name[..2]).join(&name[2..4]).join(&name),
+    }
+}
+
+fn read_index_entries(index_dir: &Path, name: &str) -> Option<Vec<IndexEntry>> {
+    let content = fs::read_to_string(index_file(index_dir, name)).ok()?;
+    Some(
+        content
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect(),
+    )
+}
+
+// Crate names may contain `-` followed by a digit too, so every split of
+// `<name>-<version>` is looked up in the index. Returns the name and version.
+fn parse_crate_file(index_dir: &Path, stem: &str) -> Result<(String, Version), Box<dyn Error>> {
+    for (i, _) in stem.match_indices('-') {
+        let (name, version) = (&stem[..i], &stem[i + 1..]);
+        if !version.starts_with(|c: char| c.is_ascii_digit()) {
+            continue;
+        }
+        let Some(entries) = read_index_entries(index_dir, name) else {
+            continue;
+        };
+        if let Some(entry) = entries.iter().find(|entry| entry.vers == version) {
+            if entry.yanked {
+                return Err(format!("{} {} is yanked", name, version).into());
+            }
+            return Ok((name.to_string(), Version::parse(version)?));
+        }
+    }
+    Err(format!("{} is not in the index", stem).into())
+}
+
+// Streams the given columns of every record of a CSV file of the crates.io
+// database dump
+fn read_columns<const N: usize>(
+    path: &Path,
+    names: [&str; N],
+) -> Result<impl Iterator<Item = [String; N]>, Box<dyn Error>> {
+    let mut reader = csv::Reader::from_path(path)?;
+    let headers = reader.headers()?.clone();
+    let mut columns = [0; N];
+    for (column, name) in columns.iter_mut().zip(names) {
+        *column = headers
+            .iter()
+            .position(|header| header == name)
+            .ok_or_else(|| format!("{:?} has no {} column", path, name))?;
+    }
+    Ok(reader
+        .into_records()
+        .filter_map(Result::ok)
+        .map(move |record| {
+            columns.map(|column| record.get(column).unwrap_or_default().to_string())
+        }))
+}
+
+/// Reads the download count of every crate from the `crates.csv` file of the
+/// crates.io database dump. Recent dumps keep them in the `crate_downloads.csv`
+/// file next to it, joined on the crate id.
+fn read_downloads(path: &Path) -> Result<HashMap<String, u64>, Box<dyn Error>> {
+    let has_downloads = csv::Reader::from_path(path)?
+        .headers()?
+        .iter()
+        .any(|header| header == "downloads");
+    if has_downloads {
+        return Ok(read_columns(path, ["name", "downloads"])?
+            .filter_map(|[name, downloads]| Some((name.to_lowercase(), downloads.parse().ok()?)))
+            .collect());
+    }
+
+    let downloads_path = path.with_file_name("crate_downloads.csv");
+    let downloads: HashMap<String, u64> = read_columns(&downloads_path, ["crate_id", "downloads"])?
+        .filter_map(|[id, downloads]| Some((id, downloads.parse().ok()?)))
+        .collect();
+    Ok(read_columns(path, ["id", "name"])?
+        .filter_map(|[id, name]| Some((name.to_lowercase(), *downloads.get(&id)?)))
+        .collect())
+}
+
+/// Selects the `.crate` files of the directory found in the index, keeping the
+/// highest version of each crate, which may have been published before others. The most downloaded ones come first.
+pub fn select_published_crates(
+    index_dir: &Path,
+    crates_dir: &Path,
+    downloads_path: Option<&Path>,
+    count: Option<usize>,
+) -> Result<Vec<PublishedCrate>, Box<dyn Error>> {
+    let downloads = match downloads_path {
+        Some(path) => read_downloads(path)?,
+        None => HashMap::new(),
+    };
+
+    let mut latest: HashMap<String, (Version, PublishedCrate)> = HashMap::new();
+    for entry in fs::read_dir(crates_dir)? {
+        let archive = entry?.path();
+        let Some(stem) = archive
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".crate"))
+        else {
+            continue;
+        };
+        let (name, version) = match parse_crate_file(index_dir, stem) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                println!("Skipping {:?}: {}", archive, e);
+                continue;
+            }
+        };
+        if latest
+            .get(&name)
+            .is_some_and(|(latest_version, _)| *latest_version > version)
+        {
+            continue;
+        }
+        let published_crate = PublishedCrate {
+            downloads: downloads.get(&name.to_lowercase()).copied().unwrap_or(0),
+            name: name.clone(),
+            version: version.to_string(),
+            archive: archive.clone(),
+        };
+        latest.insert(name, (version, published_crate));
+    }
+
+    let mut crates: Vec<PublishedCrate> = latest.into_values().map(|(_, c)| c).collect();
+    crates.sort_by(|a, b| b.downloads.cmp(&a.downloads).then(a.name.cmp(&b.name)));
+    if let Some(count) = count {
+        crates.truncate(count);
+    }
+    pretty_print("Selected published crates", Some(&crates.len()));
+    Ok(crates)
+}
+
+/// Unpacks the crates into `<name>.<version>` folders of the repositories directory.
+/// Published versions never change, so folders already unpacked are kept.
+pub async fn unpack_published_crates(
+    workspace: &Workspace,
+    state: &mut ScraperState,
+    crates: Vec<PublishedCrate>,
+) -> Result<PublishedCrates, Box<dyn Error>> {
+    let repos_path = workspace.repos_path();
+    let mut unpacked = Vec::new();
+    for published_crate in crates {
+        let path = repos_path.join(published_crate.repo_path());
+        if !path.join("Cargo.toml").exists() {
+            if let Err(e) = unpack_archive(&published_crate.archive, &path).await {
+                println!("Failed to unpack: {}", e);
+                continue;
+            }
+        }
+        unpacked.push(published_crate);
+    }
+
+    // Previously unpacked crates no longer selected, such as older versions
+    if let Some(previous) = PublishedCrates::load(workspace) {
+        for previous_crate in previous.0 {
+            let repo_path = previous_crate.repo_path();
+            if !unpacked.iter().any(|c| c.repo_path() == repo_path) {
+                let path = repos_path.join(&repo_path);
+                match fs::remove_dir_all(&path) {
+                    Ok(()) => pretty_print("Removed", Some(&path)),
+                    Err(e) => println!("Failed to remove {:?}: {}", path, e),
+                }
+            }
+        }
+    }
+
+    let crates = PublishedCrates(unpacked);
+    crates.save(workspace)?;
+    state.complete(workspace, Stage::Clone);
+    pretty_print("Published crates unpacked", Some(&crates.0.len()));
+    Ok(crates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selects_the_highest_non_yanked_version() {
+        let index = tempfile::tempdir().unwrap();
+        let crates = tempfile::tempdir().unwrap();
+        // A backport published after the highest version, and a yanked major version
+        let entries = [("1.10.0", false), ("2.0.0", true), ("1.9.1", false)];
+        let lines: Vec<String> = entries
+            .iter()
+            .map(|(vers, yanked)| {
+                serde_json::json!({ "name": "my-crate", "vers": vers, "yanked": yanked })
+                    .to_string()
+            })
+            .collect();
+        let index_file = index_file(index.path(), "my-crate");
+        fs::create_dir_all(index_file.parent().unwrap()).unwrap();
+        fs::write(&index_file, lines.join("\n")).unwrap();
+        for (vers, _) in entries {
+            fs::write(crates.path().join(format!("my-crate-{}.crate", vers)), "").unwrap();
+        }
+
+        let selected = select_published_crates(index.path(), crates.path(), None, None).unwrap();
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected[0].name, "my-crate");
+        assert_eq!(selected[0].version, "1.10.0");
+    }
+
+    #[test]
+    fn reads_downloads_of_both_dump_layouts() {
+        let dump = tempfile::tempdir().unwrap();
+        let crates_csv = dump.path().join("crates.csv");
+        let expected = HashMap::from([("serde".to_string(), 300), ("my-crate".to_string(), 2)]);
+
+        // Older dumps, with a quoted description spanning lines
+        fs::write(
+            &crates_csv,
+            "created_at,description,downloads,id,name\n\
+             2014-12-05,\"A generic, \"\"serialization\"\"\nframework\",300,1,serde\n\
+             2020-01-01,,2,2,My-Crate\n",
+        )
+        .unwrap();
+        assert_eq!(read_downloads(&crates_csv).unwrap(), expected);
+
+        // Recent dumps, keeping the downloads in their own table
+        fs::write(
+            &crates_csv,
+            "created_at,description,id,name\n\
+             2014-12-05,\"A generic, \"\"serialization\"\"\nframework\",1,serde\n\
+             2020-01-01,,2,My-Crate\n",
+        )
+        .unwrap();
+        fs::write(
+            dump.path().join("crate_downloads.csv"),
+            "crate_id,downloads\n2,2\n1,300\n",
+        )
+        .unwrap();
+        assert_eq!(read_downloads(&crates_csv).unwrap(), expected);
+    }
+}
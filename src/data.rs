@@ -5,8 +5,12 @@ use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 
 use crate::{
-    analyzis::{DeriveMacroUsage, MacroAnalyzis, MacroUsage},
-    results::{AnalyzisResults, CharLineCount},
+    analyzis::{
+        merge_macro_usage, ClassMacroUsage, ConditionalAttributes, ConditionalMacroUsage,
+        DeriveMacroUsage, MacroAnalyzis, MacroUsage, TargetMacroUsage,
+    },
+    providers::{merge_providers_usage, ProvidersMacroUsage},
+    results::{merge_source_count, AnalyzisResults, CharLineCount, ClassSourceCount},
 };
 
 #[derive(TS, Serialize, Deserialize, Default, Debug, Clone)]
@@ -25,6 +29,22 @@ struct MacroInvocationsByType {
     function_declarative_macros: u32,
 }
 
+#[derive(TS, Serialize, Deserialize, Default, Debug, Clone)]
+struct UsageByCondition {
+    unconditional: u32,
+    /// Wrapped in `cfg_attr`
+    conditional: u32,
+}
+
+#[derive(TS, Serialize, Deserialize, Default, Debug, Clone)]
+struct AttributesByCondition {
+    derive_macros: UsageByCondition,
+    attribute_macros: UsageByCondition,
+    builtin_attribute_macros: UsageByCondition,
+    derive_helpers: UsageByCondition,
+    inner_attributes: UsageByCondition,
+}
+
 #[derive(TS, Serialize, Deserialize, Default, Debug, Clone)]
 struct DeriveUsage {
     avg: f32,
@@ -49,12 +69,52 @@ pub struct Data {
     characters_per_crate: HashMap<String, usize>,
     derive_usage: DeriveUsage,
     total_macro_usage: MacroAnalyzis,
+    attributes_by_condition: AttributesByCondition,
+    macro_usage_per_target: TargetMacroUsage,
+    macro_usage_per_class: ClassMacroUsage,
+    macro_usage_per_provider: ProvidersMacroUsage,
+    source_count_per_class: ClassSourceCount,
     pub date: DateTime<Local>,
 }
 
+fn attributes_by_condition(usage: &MacroAnalyzis) -> AttributesByCondition {
+    let conditional = usage
+        .conditional_attributes
+        .0
+        .values()
+        .cloned()
+        .fold(ConditionalAttributes::default(), |acc, attributes| {
+            acc + attributes
+        });
+    let by_condition = |unconditional: &MacroUsage, conditional: MacroUsage| UsageByCondition {
+        unconditional: unconditional.clone().into(),
+        conditional: conditional.into(),
+    };
+    AttributesByCondition {
+        derive_macros: by_condition(&usage.derive_macro_usage.derives, conditional.derives),
+        attribute_macros: by_condition(
+            &usage.attribute_macro_invocations,
+            conditional.attribute_macro_invocations,
+        ),
+        builtin_attribute_macros: by_condition(
+            &usage.builtin_attribute_macro_invocations,
+            conditional.builtin_attribute_macro_invocations,
+        ),
+        derive_helpers: by_condition(
+            &usage.derive_helper_attributes,
+            conditional.derive_helper_attributes,
+        ),
+        inner_attributes: by_condition(
+            &usage.inner_attribute_invocations,
+            conditional.inner_attribute_invocations,
+        ),
+    }
+}
+
 fn calculate_statistics(sorted_data: Vec<usize>) -> DeriveUsage {
+    // Crates without any derive
     if sorted_data.is_empty() {
-        panic!(); // Handle empty input
+        return DeriveUsage::default();
     }
 
     // 1. Calculate average (as u32)
@@ -103,6 +163,13 @@ impl From<AnalyzisResults> for Data {
         let mut attribute_macro_invocations = MacroUsage(HashMap::new());
         let mut builtin_attribute_macro_invocations = MacroUsage(HashMap::new());
         let mut macro_invocations = MacroUsage(HashMap::new());
+        let mut resolved_attribute_macro_invocations = MacroUsage(HashMap::new());
+        let mut resolved_macro_invocations = MacroUsage(HashMap::new());
+        let mut nested_macro_invocations = MacroUsage(HashMap::new());
+        let mut derive_helper_attributes = MacroUsage(HashMap::new());
+        let mut conditional_attributes = ConditionalMacroUsage::default();
+        let mut inner_attribute_invocations = MacroUsage(HashMap::new());
+        let mut macro_definition_invocations = MacroUsage(HashMap::new());
         let mut derive_macro_usage = DeriveMacroUsage::default();
 
         let mut macro_invocations_by_type = MacroInvocationsByType::default();
@@ -117,6 +184,10 @@ impl From<AnalyzisResults> for Data {
         let mut macro_definitions_per_repo = vec![];
 
         let mut derives_per_invocation: Vec<usize> = vec![];
+        let mut macro_usage_per_target = TargetMacroUsage::new();
+        let mut macro_usage_per_class = ClassMacroUsage::new();
+        let mut macro_usage_per_provider = ProvidersMacroUsage::new();
+        let mut source_count_per_class = ClassSourceCount::new();
 
         for (path, repo) in value.repos.iter() {
             let macro_usage = repo
@@ -137,6 +208,32 @@ impl From<AnalyzisResults> for Data {
             builtin_attribute_macro_invocations = builtin_attribute_macro_invocations
                 + macro_usage.builtin_attribute_macro_invocations.clone();
             derive_macro_usage = derive_macro_usage + macro_usage.derive_macro_usage.clone();
+            resolved_attribute_macro_invocations = resolved_attribute_macro_invocations
+                + macro_usage.resolved_attribute_macro_invocations.clone();
+            resolved_macro_invocations =
+                resolved_macro_invocations + macro_usage.resolved_macro_invocations.clone();
+            derive_helper_attributes =
+                derive_helper_attributes + macro_usage.derive_helper_attributes.clone();
+            conditional_attributes =
+                conditional_attributes + macro_usage.conditional_attributes.clone();
+            inner_attribute_invocations =
+                inner_attribute_invocations + macro_usage.inner_attribute_invocations.clone();
+            nested_macro_invocations =
+                nested_macro_invocations + macro_usage.nested_macro_invocations.clone();
+            macro_definition_invocations =
+                macro_definition_invocations + macro_usage.macro_definition_invocations.clone();
+            if let Some(target_macro_usage) = &repo.target_macro_usage {
+                merge_macro_usage(&mut macro_usage_per_target, target_macro_usage);
+            }
+            if let Some(class_macro_usage) = &repo.class_macro_usage {
+                merge_macro_usage(&mut macro_usage_per_class, class_macro_usage);
+            }
+            if let Some(provider_macro_usage) = &repo.provider_macro_usage {
+                merge_providers_usage(&mut macro_usage_per_provider, provider_macro_usage);
+            }
+            if let Some(class_source_count) = &repo.class_source_count {
+                merge_source_count(&mut source_count_per_class, class_source_count);
+            }
 
             let macro_invocations: u32 = (macro_usage.macro_invocations
                 + macro_usage.attribute_macro_invocations
@@ -201,9 +298,22 @@ impl From<AnalyzisResults> for Data {
             macro_invocations,
             derive_macro_usage,
             derive_macro_definitions,
+            resolved_attribute_macro_invocations,
+            resolved_macro_invocations,
+            derive_helper_attributes,
+            inner_attribute_invocations,
+            conditional_attributes,
+            nested_macro_invocations,
+            macro_definition_invocations,
         };
+        let attributes_by_condition = attributes_by_condition(&total_macro_usage);
         Self {
             total_macro_usage,
+            attributes_by_condition,
+            macro_usage_per_target,
+            macro_usage_per_class,
+            macro_usage_per_provider,
+            source_count_per_class,
             macro_invocations_per_repo,
             macro_definitions_per_repo,
             macro_definitions_per_crate,
@@ -218,3 +328,15 @@ impl From<AnalyzisResults> for Data {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exports_crates_without_derives() {
+        let data = Data::from(AnalyzisResults::default());
+        assert!(data.derive_usage.sorted_data.is_empty());
+        assert_eq!(data.derive_usage.max, 0);
+    }
+}
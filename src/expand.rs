@@ -1,12 +1,16 @@
 use crate::{
-    cargo::CargoToml, crate_paths::get_repo_path, results::AnalyzisResults, state::ScraperState,
+    cargo::CargoToml,
+    crate_paths::get_repo_path,
+    git::CloneStrategy,
+    results::AnalyzisResults,
+    state::{ScraperState, Stage},
     utils::pretty_print,
+    workspace::Workspace,
 };
-use chrono::Local;
 use futures::future::join_all;
 use std::{
     error::Error,
-    path::Path,
+    path::{Path, PathBuf},
     sync::{
         atomic::{AtomicUsize, Ordering},
         Arc,
@@ -16,8 +20,7 @@ use tokio::{fs, io::AsyncWriteExt, sync::Semaphore, task};
 
 const WORKER_POOL_SIZE: usize = 10;
 
-pub async fn expand_crate(path: String) -> Result<(), String> {
-    let crate_path = Path::new("./data/repos").join(path);
+pub async fn expand_crate(crate_path: &Path) -> Result<(), String> {
     let cargo_path = crate_path.join("Cargo.toml");
 
     let cargo_toml = fs::read_to_string(cargo_path.clone())
@@ -40,9 +43,9 @@ pub async fn expand_crate(path: String) -> Result<(), String> {
     if cargo_toml.lib.is_some() {
         command.arg("--lib");
     } else if let Some(bin_entries) = cargo_toml.bin {
-        if let Some(first_bin) = bin_entries.first() {
+        if let Some(name) = bin_entries.first().and_then(|bin| bin.name.clone()) {
             command.arg("--bin");
-            command.arg(first_bin.name.clone());
+            command.arg(name);
         }
     }
 
@@ -62,13 +65,14 @@ pub async fn expand_crate(path: String) -> Result<(), String> {
     Ok(())
 }
 
-async fn expand_crate_task(path: String) -> (String, Result<(), String>) {
-    let result = expand_crate(path.clone()).await;
+async fn expand_crate_task(crate_dir: PathBuf, path: String) -> (String, Result<(), String>) {
+    let result = expand_crate(&crate_dir).await;
 
     (path, result)
 }
 
 pub async fn expand_crates(
+    workspace: &Workspace,
     state: &mut ScraperState,
     analyzis_results: &mut AnalyzisResults,
 ) -> Result<(), Box<dyn Error>> {
@@ -79,6 +83,13 @@ pub async fn expand_crates(
         );
         return Ok(());
     }
+    analyzis_results.clear_expanded_count();
+    if state.clone_strategy == CloneStrategy::Sparse {
+        pretty_print(
+            "Expanding sparse clones, crates needing other files will fail",
+            None,
+        );
+    }
 
     let semaphore = Arc::new(Semaphore::new(WORKER_POOL_SIZE));
     let counter = Arc::new(AtomicUsize::new(0));
@@ -90,6 +101,7 @@ pub async fn expand_crates(
             let semaphore_clone = semaphore.clone();
             let counter_clone = counter.clone();
             let path_string = path.to_string();
+            let crate_dir = workspace.crate_dir(&path);
 
             task::spawn(async move {
                 let _permit = semaphore_clone
@@ -99,7 +111,7 @@ pub async fn expand_crates(
                 let count = counter_clone.fetch_add(1, Ordering::Relaxed);
                 pretty_print("Expanded crates", Some(&count));
                 pretty_print("Expanding crate", Some(&path_string));
-                expand_crate_task(path_string).await
+                expand_crate_task(crate_dir, path_string).await
             })
         })
         .collect();
@@ -131,9 +143,9 @@ pub async fn expand_crates(
         }
     }
 
-    state.expanded_macros_at = Some(Local::now());
-    state.save()?;
-    analyzis_results.save()?;
+    state.complete(workspace, Stage::Expand);
+    state.save(workspace)?;
+    analyzis_results.save(workspace)?;
     pretty_print("Macros expanded", None);
     Ok(())
 }
@@ -0,0 +1,194 @@
+use std::{collections::BTreeMap, error::Error, path::Path};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::ignore::IGNORE_FILE_NAME;
+
+// Every file the analysis stages read. Expanding macros may need more, such as
+// files pulled in by `include_str!`
+const SPARSE_PATTERNS: [&str; 3] = ["*.rs", "Cargo.toml", IGNORE_FILE_NAME];
+
+/// How much of each repository is downloaded when cloning
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CloneStrategy {
+    /// Full history of the repository and its submodules
+    #[default]
+    Full,
+    /// Only the latest commit of the repository and its submodules
+    Shallow,
+    /// Full history, downloading file contents of the checked out commit only
+    Blobless,
+    /// Blobless clone checking out only `*.rs`, `Cargo.toml` and `.macroanalyzerignore`
+    /// files, and submodules.
+    /// Not enough for `expand`, which can also need `Cargo.lock`, build script inputs
+    /// and `include_str!` targets
+    Sparse,
+}
+
+impl CloneStrategy {
+    pub fn clone_args(self) -> &'static [&'static str] {
+        match self {
+            CloneStrategy::Full => &["--recurse-submodules", "-j8"],
+            CloneStrategy::Shallow => &[
+                "--depth",
+                "1",
+                "--recurse-submodules",
+                "--shallow-submodules",
+                "-j8",
+            ],
+            CloneStrategy::Blobless => &["--filter=blob:none", "--recurse-submodules", "-j8"],
+            // The sparse checkout has to be set before checking out
+            CloneStrategy::Sparse => &["--filter=blob:none", "--no-checkout"],
+        }
+    }
+}
+
+/// Commit checked out in a repository, used to clone the exact same code again
+#[derive(TS, Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct RepoRevision {
+    pub head: String,
+    /// `None` when the HEAD is detached
+    pub branch: Option<String>,
+    /// Commit of every submodule, by path relative to the repository
+    pub submodules: BTreeMap<String, String>,
+}
+
+pub async fn git(dir: &Path, args: &[&str]) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let output = tokio::process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(format!(
+            "git {} failed in {:?}: {}",
+            args.join(" "),
+            dir,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .trim_end()
+        .to_string())
+}
+
+pub async fn read_revision(dir: &Path) -> Result<RepoRevision, Box<dyn Error + Send + Sync>> {
+    let head = git(dir, &["rev-parse", "HEAD"]).await?;
+    let branch = git(dir, &["rev-parse", "--abbrev-ref", "HEAD"]).await?;
+    let mut submodules = BTreeMap::new();
+    // Lines are `<status><sha> <path> (<describe>)`, the status being a single character
+    for line in git(dir, &["submodule", "status", "--recursive"])
+        .await?
+        .lines()
+    {
+        let mut parts = line.get(1..).unwrap_or_default().split_whitespace();
+        if let (Some(sha), Some(path)) = (parts.next(), parts.next()) {
+            submodules.insert(path.to_string(), sha.to_string());
+        }
+    }
+    Ok(RepoRevision {
+        head,
+        branch: (branch != "HEAD").then_some(branch),
+        submodules,
+    })
+}
+
+/// Checks out the files of the strategy. Only sparse clones are limited, so a
+/// repository cloned with another strategy before gets every file back
+pub async fn apply_checkout_strategy(
+    dir: &Path,
+    strategy: CloneStrategy,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if strategy != CloneStrategy::Sparse {
+        if git(dir, &["config", "--bool", "core.sparseCheckout"])
+            .await
+            .is_ok_and(|sparse| sparse == "true")
+        {
+            git(dir, &["sparse-checkout", "disable"]).await?;
+        }
+        return Ok(());
+    }
+    let mut args: Vec<String> = ["sparse-checkout", "set", "--no-cone"]
+        .into_iter()
+        .chain(SPARSE_PATTERNS)
+        .map(str::to_string)
+        .collect();
+    // Submodules are gitlinks, which the file patterns don't match
+    let gitmodules = git(
+        dir,
+        &[
+            "config",
+            "--blob",
+            "HEAD:.gitmodules",
+            "--get-regexp",
+            r"\.path$",
+        ],
+    )
+    .await;
+    for line in gitmodules.unwrap_or_default().lines() {
+        if let Some((_, path)) = line.split_once(' ') {
+            args.push(format!("/{}", path));
+        }
+    }
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    git(dir, &args).await?;
+    git(dir, &["checkout"]).await?;
+    git(dir, &["submodule", "update", "--init", "--recursive"]).await?;
+    Ok(())
+}
+
+/// Fetches an existing clone and hard resets it to the remote default branch,
+/// discarding local changes
+pub async fn reset_to_default_branch(
+    dir: &Path,
+    strategy: CloneStrategy,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    match strategy {
+        CloneStrategy::Shallow => git(dir, &["fetch", "--prune", "--depth", "1", "origin"]).await?,
+        _ => git(dir, &["fetch", "--prune", "origin"]).await?,
+    };
+    // The default branch may have been renamed since the repository was cloned
+    git(dir, &["remote", "set-head", "origin", "--auto"]).await?;
+    let remote_head = git(dir, &["rev-parse", "--abbrev-ref", "origin/HEAD"]).await?;
+    let branch = remote_head.trim_start_matches("origin/");
+    git(dir, &["checkout", "--force", "-B", branch, &remote_head]).await?;
+    apply_checkout_strategy(dir, strategy).await?;
+    git(dir, &["submodule", "sync", "--recursive"]).await?;
+    git(
+        dir,
+        &["submodule", "update", "--init", "--recursive", "--force"],
+    )
+    .await?;
+    Ok(())
+}
+
+// Fetches the commit only when the clone doesn't have it, e.g. after a force push
+async fn checkout_commit(dir: &Path, sha: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let commit = format!("{}^{{commit}}", sha);
+    if git(dir, &["cat-file", "-e", &commit]).await.is_err() {
+        git(dir, &["fetch", "origin", sha]).await?;
+    }
+    git(dir, &["checkout", "--detach", sha]).await?;
+    Ok(())
+}
+
+/// Checks out the recorded HEAD and submodule commits of a cloned repository
+pub async fn checkout_revision(
+    dir: &Path,
+    revision: &RepoRevision,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    checkout_commit(dir, &revision.head).await?;
+    git(dir, &["submodule", "update", "--init", "--recursive"]).await?;
+    for (path, sha) in &revision.submodules {
+        checkout_commit(&dir.join(path), sha).await?;
+    }
+    let checked_out = read_revision(dir).await?;
+    if checked_out.head != revision.head || checked_out.submodules != revision.submodules {
+        return Err(format!("{:?} does not match the recorded revision", dir).into());
+    }
+    Ok(())
+}
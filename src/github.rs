@@ -1,22 +1,34 @@
+use crate::git::{
+    apply_checkout_strategy, checkout_revision, git, read_revision, reset_to_default_branch,
+    CloneStrategy, RepoRevision,
+};
+use crate::source::{unpack_archive, RepoSource};
+use crate::state::{ScraperState, Stage};
 use crate::utils::pretty_print;
-use crate::ScraperState;
-use chrono::Local;
+use crate::workspace::Workspace;
+use futures::stream::{FuturesUnordered, StreamExt};
 use graphql_client::{GraphQLQuery, Response};
-use reqwest::{header, Client};
-use std::sync::Arc;
+use reqwest::{header, header::HeaderMap, Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::time::Duration;
 use std::{
+    collections::{BTreeMap, HashSet},
     error::Error,
-    fs::File,
-    io::{Read, Write},
     path::Path,
 };
 use tokio::sync::Semaphore;
 
-const GRAPHQL_URL: &str = "https://api.github.com/graphql";
+pub const GRAPHQL_URL: &str = "https://api.github.com/graphql";
 const USER_AGENT: &str = "NiloDrumond (https://github.com/NiloDrumond)";
-const REPOS_PATH: &str = "./data/repos.ron";
-const CLONED_REPOS_PATH: &str = "./data/repos";
-const REPOS_TO_FETCH: i64 = 100;
+const REPOS_TO_FETCH: usize = 100;
+const LANGUAGE_QUALIFIER: &str = "language:Rust";
+const MAX_QUERY_LENGTH: usize = 256;
+const PAGE_SIZE: i64 = 100;
+const MAX_SEARCH_RESULTS: usize = 1000;
+const MAX_RETRIES: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
 
 const WORKER_POOL_SIZE: usize = 30;
 
@@ -29,15 +41,16 @@ pub type Repository = repos_query::ReposQuerySearchNodesOnRepository;
 #[graphql(
     schema_path = "graphql/github_schema.json",
     query_path = "graphql/repos_query.graphql",
-    response_derives = "Debug,Serialize,Deserialize"
+    response_derives = "Debug,Clone,Serialize,Deserialize"
 )]
 pub struct ReposQuery;
 
-impl From<repos_query::ResponseData> for Vec<Repository> {
-    fn from(val: repos_query::ResponseData) -> Self {
-        val.search
-            .nodes
-            .unwrap()
+impl TryFrom<repos_query::ResponseData> for Vec<Repository> {
+    type Error = GithubError;
+
+    fn try_from(val: repos_query::ResponseData) -> Result<Self, Self::Error> {
+        let nodes = val.search.nodes.ok_or(GithubError::MissingNodes)?;
+        Ok(nodes
             .into_iter()
             .filter_map(|repo| {
                 if let Some(repo) = repo {
@@ -50,84 +63,560 @@ impl From<repos_query::ResponseData> for Vec<Repository> {
                 }
                 None
             })
-            .collect()
+            .collect())
+    }
+}
+
+#[derive(Debug)]
+pub enum GithubError {
+    /// The token is missing, invalid or lacks the required scopes
+    Unauthorized(String),
+    /// Primary or secondary rate limit, with the time to wait before retrying
+    RateLimited {
+        message: String,
+        wait: Duration,
+    },
+    Http {
+        status: StatusCode,
+        message: String,
+    },
+    /// Messages of the GraphQL `errors` array
+    GraphQL(Vec<String>),
+    MissingData,
+    MissingNodes,
+    Request(reqwest::Error),
+}
+
+impl GithubError {
+    fn is_retryable(&self) -> bool {
+        match self {
+            GithubError::RateLimited { .. } => true,
+            GithubError::Http { status, .. } => status.is_server_error(),
+            GithubError::Request(e) => !e.is_decode(),
+            _ => false,
+        }
+    }
+
+    // Waits the time requested by GitHub, or backs off exponentially otherwise
+    fn retry_delay(&self, attempt: u32) -> Duration {
+        match self {
+            GithubError::RateLimited { wait, .. } => *wait,
+            _ => (INITIAL_BACKOFF * 2u32.pow(attempt)).min(MAX_BACKOFF),
+        }
     }
 }
 
-fn load_popular_repos() -> Option<repos_query::ResponseData> {
-    let mut state_file = match File::open(REPOS_PATH) {
-        Ok(file) => file,
-        Err(_) => return None,
+impl std::fmt::Display for GithubError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GithubError::Unauthorized(message) => write!(f, "GitHub unauthorized: {}", message),
+            GithubError::RateLimited { message, wait } => {
+                write!(f, "GitHub rate limited for {:?}: {}", wait, message)
+            }
+            GithubError::Http { status, message } => write!(f, "GitHub {}: {}", status, message),
+            GithubError::GraphQL(messages) => {
+                write!(f, "GitHub GraphQL errors: {}", messages.join("; "))
+            }
+            GithubError::MissingData => write!(f, "GitHub response has no data"),
+            GithubError::MissingNodes => write!(f, "GitHub search response has no nodes"),
+            GithubError::Request(e) => write!(f, "GitHub request failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for GithubError {}
+
+impl From<reqwest::Error> for GithubError {
+    fn from(e: reqwest::Error) -> Self {
+        GithubError::Request(e)
+    }
+}
+
+// Error body of the REST-style responses, such as 401 and 403
+#[derive(Deserialize, Default)]
+struct GithubErrorBody {
+    #[serde(default)]
+    message: String,
+}
+
+// `Retry-After` takes precedence, then `X-RateLimit-Reset` once the quota is exhausted
+fn rate_limit_wait(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<i64>().ok())
     };
-    let mut buf: String = String::new();
-    if let Err(e) = state_file.read_to_string(&mut buf) {
-        println!("Error reading file: {:?}", e);
+    if let Some(seconds) = header("retry-after") {
+        return Some(Duration::from_secs(seconds.max(0) as u64));
+    }
+    let reset = header("x-ratelimit-reset")?;
+    if header("x-ratelimit-remaining").is_some_and(|remaining| remaining > 0) {
         return None;
     }
-    match ron::from_str::<repos_query::ResponseData>(&buf) {
-        Ok(state) => Some(state),
-        Err(e) => {
-            println!("Error deserializing RON: {:?}", e);
-            None
+    let seconds = reset - chrono::Utc::now().timestamp();
+    Some(Duration::from_secs(seconds.max(1) as u64))
+}
+
+fn is_rate_limit_message(message: &str) -> bool {
+    message.to_lowercase().contains("rate limit")
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct PopularRepos(pub Vec<Repository>);
+
+impl_save_load!(PopularRepos, Workspace::popular_repos_path);
+
+/// Repository and the commits it was cloned at
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PinnedRepo {
+    pub repository: Repository,
+    pub revision: RepoRevision,
+}
+
+impl PinnedRepo {
+    pub fn repo_path(&self) -> String {
+        repo_folder_name(&self.repository)
+    }
+}
+
+/// Written by the clone stage, so the dataset can be cloned again at the same commits
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct CloneManifest(pub Vec<PinnedRepo>);
+
+impl_save_load!(CloneManifest, Workspace::manifest_path);
+
+impl CloneManifest {
+    pub fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(ron::from_str(&content)?)
+    }
+}
+
+/// Which repositories are fetched from GitHub
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct RepoSelection {
+    pub count: usize,
+    pub min_stars: i64,
+    pub topics: Vec<String>,
+    pub orgs: Vec<String>,
+    /// Date or range in GitHub search syntax, e.g. `>=2020-01-01` or `2020-01-01..2021-01-01`
+    pub created: Option<String>,
+    /// Date or range in GitHub search syntax
+    pub pushed: Option<String>,
+    pub license: Option<String>,
+    /// Explicit `owner/name` list. When set, every listed repository is fetched, ignoring `count`
+    /// and the search qualifiers
+    pub repos: Vec<String>,
+}
+
+impl Default for RepoSelection {
+    fn default() -> Self {
+        Self {
+            count: REPOS_TO_FETCH,
+            min_stars: 1,
+            topics: Vec::new(),
+            orgs: Vec::new(),
+            created: None,
+            pushed: None,
+            license: None,
+            repos: Vec::new(),
+        }
+    }
+}
+
+impl RepoSelection {
+    fn qualifiers(&self) -> String {
+        let mut qualifiers = vec![LANGUAGE_QUALIFIER.to_string()];
+        qualifiers.extend(self.topics.iter().map(|topic| format!("topic:{}", topic)));
+        qualifiers.extend(self.orgs.iter().map(|org| format!("org:{}", org)));
+        if let Some(created) = &self.created {
+            qualifiers.push(format!("created:{}", created));
+        }
+        if let Some(pushed) = &self.pushed {
+            qualifiers.push(format!("pushed:{}", pushed));
+        }
+        if let Some(license) = &self.license {
+            qualifiers.push(format!("license:{}", license));
+        }
+        qualifiers.join(" ")
+    }
+
+    fn search_query(&self, extra_qualifiers: &str, max_stars: Option<i64>) -> String {
+        // Repositories named explicitly are fetched whatever their language or stars
+        if !self.repos.is_empty() {
+            return format!("{} sort:stars-desc", extra_qualifiers);
+        }
+        let stars = match max_stars {
+            Some(max_stars) => format!("stars:{}..{}", self.min_stars, max_stars),
+            None => format!("stars:>={}", self.min_stars),
+        };
+        let mut query = self.qualifiers();
+        if !extra_qualifiers.is_empty() {
+            query = format!("{} {}", query, extra_qualifiers);
+        }
+        format!("{} {} sort:stars-desc", query, stars)
+    }
+
+    // Splits the explicit repository list into `repo:` qualifiers that fit in a search query
+    fn repo_batches(&self) -> Vec<String> {
+        let max_len = MAX_QUERY_LENGTH.saturating_sub(self.search_query("", Some(i64::MAX)).len());
+        let mut batches: Vec<String> = Vec::new();
+        let mut batch = String::new();
+        for repo in &self.repos {
+            let qualifier = format!("repo:{}", repo);
+            if !batch.is_empty() && batch.len() + qualifier.len() + 1 > max_len {
+                batches.push(std::mem::take(&mut batch));
+            }
+            if !batch.is_empty() {
+                batch.push(' ');
+            }
+            batch.push_str(&qualifier);
+        }
+        if !batch.is_empty() {
+            batches.push(batch);
+        }
+        batches
+    }
+}
+
+struct GithubClient {
+    client: Client,
+    graphql_url: String,
+    token: String,
+}
+
+impl GithubClient {
+    fn new(workspace: &Workspace) -> Result<Self, Box<dyn Error>> {
+        let graphql_url = workspace.graphql_url().to_string();
+        let token = match std::env::var("GITHUB_TOKEN") {
+            Ok(token) => token,
+            // Only GitHub itself needs a token, not the endpoints standing in for it
+            Err(_) if graphql_url != GRAPHQL_URL => String::new(),
+            Err(e) => return Err(format!("GITHUB_TOKEN: {}", e).into()),
+        };
+        Ok(Self {
+            client: Client::new(),
+            graphql_url,
+            token,
+        })
+    }
+
+    async fn search_page(
+        &self,
+        qstr: &str,
+        after: Option<String>,
+    ) -> Result<repos_query::ResponseData, GithubError> {
+        let mut attempt = 0;
+        loop {
+            match self.try_search_page(qstr, after.clone()).await {
+                Err(e) if e.is_retryable() && attempt < MAX_RETRIES => {
+                    let delay = e.retry_delay(attempt);
+                    let title = format!("Retrying GitHub request in {:?}", delay);
+                    pretty_print(&title, Some(&e.to_string()));
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn try_search_page(
+        &self,
+        qstr: &str,
+        after: Option<String>,
+    ) -> Result<repos_query::ResponseData, GithubError> {
+        let request_body = ReposQuery::build_query(repos_query::Variables {
+            qstr: qstr.to_string(),
+            first: PAGE_SIZE,
+            after,
+        });
+        let request = self
+            .client
+            .post(&self.graphql_url)
+            .header(
+                reqwest::header::AUTHORIZATION,
+                format!("token {}", self.token),
+            )
+            .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+            .header(header::USER_AGENT, USER_AGENT)
+            .json(&request_body);
+
+        let res = request.send().await?;
+        let status = res.status();
+        let wait = rate_limit_wait(res.headers());
+        if !status.is_success() {
+            let message = res
+                .json::<GithubErrorBody>()
+                .await
+                .unwrap_or_default()
+                .message;
+            return Err(match status {
+                StatusCode::UNAUTHORIZED => GithubError::Unauthorized(message),
+                StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS
+                    if wait.is_some() || is_rate_limit_message(&message) =>
+                {
+                    GithubError::RateLimited {
+                        message,
+                        wait: wait.unwrap_or(MAX_BACKOFF),
+                    }
+                }
+                _ => GithubError::Http { status, message },
+            });
+        }
+
+        let response_body: Response<repos_query::ResponseData> = res.json().await?;
+        if let Some(errors) = response_body.errors.filter(|errors| !errors.is_empty()) {
+            let messages: Vec<String> = errors.into_iter().map(|e| e.message).collect();
+            if messages
+                .iter()
+                .any(|message| is_rate_limit_message(message))
+            {
+                return Err(GithubError::RateLimited {
+                    message: messages.join("; "),
+                    wait: wait.unwrap_or(MAX_BACKOFF),
+                });
+            }
+            return Err(GithubError::GraphQL(messages));
+        }
+        response_body.data.ok_or(GithubError::MissingData)
+    }
+}
+
+// GitHub search returns at most 1000 results per query. To go beyond that, the
+// results are fetched in star ranges: once a range hits the cap, the next one
+// starts at the lowest star count seen so far. Overlapping results are deduplicated.
+async fn fetch_star_ranges(
+    github: &GithubClient,
+    selection: &RepoSelection,
+    extra_qualifiers: &str,
+    count: usize,
+    repos: &mut Vec<Repository>,
+    ids: &mut HashSet<String>,
+) -> Result<(), Box<dyn Error>> {
+    let mut new_repos = 0;
+    let mut max_stars: Option<i64> = None;
+    while new_repos < count {
+        let qstr = selection.search_query(extra_qualifiers, max_stars);
+        let mut after = None;
+        let mut fetched = 0;
+        let mut min_stars: Option<i64> = None;
+        loop {
+            let data = github.search_page(&qstr, after).await?;
+            let has_next_page = data.search.page_info.has_next_page;
+            let end_cursor = data.search.page_info.end_cursor.clone();
+            let page: Vec<Repository> = data.try_into()?;
+            fetched += page.len();
+            for repo in page {
+                let stars = repo.stargazers.total_count;
+                min_stars = Some(min_stars.map_or(stars, |min| min.min(stars)));
+                if ids.insert(repo.id.clone()) {
+                    repos.push(repo);
+                    new_repos += 1;
+                }
+            }
+            if new_repos >= count || !has_next_page || fetched >= MAX_SEARCH_RESULTS {
+                break;
+            }
+            after = end_cursor;
+        }
+        pretty_print("Fetched repositories", Some(&repos.len()));
+
+        if fetched < MAX_SEARCH_RESULTS {
+            break;
+        }
+        let next_max_stars = match min_stars {
+            // More than MAX_SEARCH_RESULTS repositories share this star count,
+            // the remaining ones are unreachable through search.
+            Some(min_stars) if Some(min_stars) == max_stars => min_stars - 1,
+            Some(min_stars) => min_stars,
+            None => break,
+        };
+        if next_max_stars < selection.min_stars {
+            break;
         }
+        max_stars = Some(next_max_stars);
     }
+    Ok(())
 }
 
 async fn fetch_most_popular_repos(
+    workspace: &Workspace,
     state: &mut ScraperState,
 ) -> Result<Vec<Repository>, Box<dyn Error>> {
-    let github_token = std::env::var("GITHUB_TOKEN")?;
-    let request_body = ReposQuery::build_query(repos_query::Variables {
-        qstr: "language:Rust stars:>=1 sort:stars-desc".to_string(),
-        first: REPOS_TO_FETCH,
-        after: None,
-    });
-    let client = Client::new()
-        .post(GRAPHQL_URL)
-        .header(
-            reqwest::header::AUTHORIZATION,
-            format!("token {}", github_token),
+    let github = GithubClient::new(workspace)?;
+    let selection = state.repos_selection.clone();
+    if selection.repos.is_empty() {
+        pretty_print(
+            "Fetching repositories matching",
+            Some(&selection.qualifiers()),
+        );
+    } else {
+        pretty_print("Fetching repositories", Some(&selection.repos.len()));
+    }
+
+    let mut repos: Vec<Repository> = Vec::new();
+    let mut ids = HashSet::new();
+    if selection.repos.is_empty() {
+        fetch_star_ranges(
+            &github,
+            &selection,
+            "",
+            selection.count,
+            &mut repos,
+            &mut ids,
         )
-        .header(reqwest::header::ACCEPT, "application/vnd.github+json")
-        .header(header::USER_AGENT, USER_AGENT)
-        .json(&request_body);
+        .await?;
+    } else {
+        for batch in selection.repo_batches() {
+            fetch_star_ranges(
+                &github,
+                &selection,
+                &batch,
+                usize::MAX,
+                &mut repos,
+                &mut ids,
+            )
+            .await?;
+        }
+        for name in &selection.repos {
+            if !repos
+                .iter()
+                .any(|repo| repo.name_with_owner.eq_ignore_ascii_case(name))
+            {
+                pretty_print("Repository not found", Some(name));
+            }
+        }
+    }
 
-    let res = client.send().await?;
-    let response_body: Response<repos_query::ResponseData> = res.json().await?;
-    let data = response_body.data.unwrap();
-    let ron_string = ron::ser::to_string_pretty(&data, ron::ser::PrettyConfig::default())?;
-    let mut file = File::create(REPOS_PATH)?;
-    state.repos_query_at = Some(Local::now());
-    state.save()?;
-    file.write_all(ron_string.as_bytes())?;
-    Ok(data.into())
+    repos.sort_by_key(|repo| std::cmp::Reverse(repo.stargazers.total_count));
+    if selection.repos.is_empty() {
+        repos.truncate(selection.count);
+    }
+    let repos = PopularRepos(repos);
+    repos.save(workspace)?;
+    state.complete(workspace, Stage::Fetch);
+    state.save(workspace)?;
+    Ok(repos.0)
 }
 
 pub async fn get_most_popular_repos(
+    workspace: &Workspace,
     state: &mut ScraperState,
 ) -> Result<Vec<Repository>, Box<dyn Error>> {
     if state.repos_query_at.is_some() {
-        if let Some(data) = load_popular_repos() {
-            let repos: Vec<Repository> = data.into();
-            pretty_print("Loaded popular repositories", Some(&repos.len()));
-            return Ok(repos);
+        if let Some(repos) = PopularRepos::load(workspace) {
+            pretty_print("Loaded popular repositories", Some(&repos.0.len()));
+            return Ok(repos.0);
         }
     }
 
-    let repos = fetch_most_popular_repos(state).await?;
+    let repos = fetch_most_popular_repos(workspace, state).await?;
     pretty_print("Fetched popular repositories", Some(&repos.len()));
     Ok(repos)
 }
 
-async fn clone_repo(repository: &Repository) -> Result<(), Box<dyn Error + Send + Sync>> {
-    let output_folder_name = format!("{}.{}", repository.owner.login, repository.name);
-    let path = Path::new(CLONED_REPOS_PATH).join(output_folder_name);
+/// `owner.name` folder the repository is cloned into
+pub fn repo_folder_name(repository: &Repository) -> String {
+    repository.name_with_owner.replacen('/', ".", 1)
+}
+
+/// Outcome of cloning a repository, persisted so that failed ones are left out
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum CloneStatus {
+    /// Newly cloned, unpacked or updated to new commits
+    Ok,
+    /// Existing checkout already at the latest commits, or already unpacked archive
+    Skipped,
+    Failed {
+        stderr: String,
+    },
+}
+
+/// Clone status of every repository by repository path
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct CloneReport(pub BTreeMap<String, CloneStatus>);
+
+impl_save_load!(CloneReport, Workspace::clone_report_path);
+
+impl CloneReport {
+    /// Whether the repository can be analyzed. Repositories missing from the
+    /// report were cloned before it was recorded.
+    pub fn is_cloned(&self, repo_path: &str) -> bool {
+        !matches!(self.0.get(repo_path), Some(CloneStatus::Failed { .. }))
+    }
+
+    /// Whether the latest clone left the repository as it was
+    pub fn is_unchanged(&self, repo_path: &str) -> bool {
+        matches!(self.0.get(repo_path), Some(CloneStatus::Skipped))
+    }
+
+    fn repos_with_status(&self, matches: fn(&CloneStatus) -> bool) -> Vec<&String> {
+        self.0
+            .iter()
+            .filter(|(_, status)| matches(status))
+            .map(|(repo_path, _)| repo_path)
+            .collect()
+    }
+
+    fn print_summary(&self) {
+        let cloned = self.repos_with_status(|status| *status == CloneStatus::Ok);
+        let skipped = self.repos_with_status(|status| *status == CloneStatus::Skipped);
+        let failed = self.repos_with_status(|status| matches!(status, CloneStatus::Failed { .. }));
+        pretty_print("Repositories cloned or changed", Some(&cloned.len()));
+        if !skipped.is_empty() {
+            pretty_print("Repositories unchanged", Some(&skipped.len()));
+        }
+        if !failed.is_empty() {
+            pretty_print("Repositories failed to clone", Some(&failed));
+        }
+    }
+}
+
+// Existing checkouts are updated instead, so clearing the clone stage doesn't
+// require deleting the repositories
+async fn clone_repo(
+    repos_path: &Path,
+    repository: &Repository,
+    source: Option<&str>,
+    strategy: CloneStrategy,
+) -> Result<CloneStatus, Box<dyn Error + Send + Sync>> {
+    let path = repos_path.join(repo_folder_name(repository));
+    let url = match RepoSource::resolve(source, repository) {
+        RepoSource::Git(url) => url,
+        RepoSource::Archive(archive) => {
+            return Ok(match unpack_archive(&archive, &path).await? {
+                true => CloneStatus::Ok,
+                false => CloneStatus::Skipped,
+            });
+        }
+    };
+    if path.join(".git").exists() {
+        let previous = read_revision(&path).await.ok();
+        // The source may have changed since the repository was cloned
+        git(&path, &["remote", "set-url", "origin", &url]).await?;
+        reset_to_default_branch(&path, strategy).await?;
+        let current = read_revision(&path).await?;
+        let changed = previous.is_none_or(|previous| {
+            previous.head != current.head || previous.submodules != current.submodules
+        });
+        return Ok(match changed {
+            true => CloneStatus::Ok,
+            false => CloneStatus::Skipped,
+        });
+    }
+    if path.exists() {
+        // Unpacked from an archive, which can't be updated in place
+        std::fs::remove_dir_all(&path)?;
+    }
+
     let output = tokio::process::Command::new("git")
         .arg("clone")
-        .arg("--recurse-submodules")
-        .arg("-j8")
-        .arg(repository.url.clone())
-        .arg(path)
+        .args(strategy.clone_args())
+        .arg(url)
+        .arg(&path)
         .stdout(std::io::stdout())
         .output()
         .await?;
@@ -135,44 +624,490 @@ async fn clone_repo(repository: &Repository) -> Result<(), Box<dyn Error + Send
     if !output.status.success() {
         return Err(String::from_utf8_lossy(&output.stderr).into());
     }
-    Ok(())
+    apply_checkout_strategy(&path, strategy).await?;
+    Ok(CloneStatus::Ok)
+}
+
+// Runs WORKER_POOL_SIZE clones at a time, printing the progress as they finish
+async fn run_clones<F>(tasks: Vec<(String, F)>) -> CloneReport
+where
+    F: Future<Output = Result<CloneStatus, Box<dyn Error + Send + Sync>>>,
+{
+    let semaphore = Semaphore::new(WORKER_POOL_SIZE);
+    let total = tasks.len();
+    let mut running: FuturesUnordered<_> = tasks
+        .into_iter()
+        .map(|(repo_path, task)| {
+            let semaphore = &semaphore;
+            async move {
+                let _permit = semaphore
+                    .acquire()
+                    .await
+                    .unwrap_or_else(|_| panic!("Failed to acquire permit"));
+                (repo_path, task.await)
+            }
+        })
+        .collect();
+
+    let mut report = CloneReport::default();
+    while let Some((repo_path, result)) = running.next().await {
+        let status = result.unwrap_or_else(|err| CloneStatus::Failed {
+            stderr: err.to_string().trim().to_string(),
+        });
+        let title = format!("{}/{} {}", report.0.len() + 1, total, repo_path);
+        pretty_print(&title, Some(&status));
+        report.0.insert(repo_path, status);
+    }
+    report
+}
+
+// Records the checked out commits of the cloned repositories
+async fn pin_repos(
+    repos_path: &Path,
+    repositories: &[Repository],
+    report: &CloneReport,
+) -> CloneManifest {
+    let mut pinned = Vec::new();
+    for repository in repositories {
+        let repo_path = repo_folder_name(repository);
+        let path = repos_path.join(&repo_path);
+        // Repositories unpacked from archives have no history to pin
+        if !report.is_cloned(&repo_path) || !path.join(".git").exists() {
+            continue;
+        }
+        match read_revision(&path).await {
+            Ok(revision) => pinned.push(PinnedRepo {
+                repository: repository.clone(),
+                revision,
+            }),
+            Err(err) => println!("Failed to read revision: {}", err),
+        }
+    }
+    CloneManifest(pinned)
 }
 
 pub async fn clone_repos(
+    workspace: &Workspace,
     state: &mut ScraperState,
     repositories: &[Repository],
-) -> Result<String, Box<dyn Error>> {
+) -> Result<CloneReport, Box<dyn Error>> {
     if state.cloned_repos_at.is_some() {
         pretty_print("Repos already cloned at", Some(&state.cloned_repos_at));
-        return Ok(CLONED_REPOS_PATH.to_string());
+        return Ok(CloneReport::load(workspace).unwrap_or_default());
     }
 
-    let semaphore = Arc::new(Semaphore::new(WORKER_POOL_SIZE));
+    let repos_path = workspace.repos_path();
+    let strategy = state.clone_strategy;
+    let source = state.clone_source.as_deref();
+    pretty_print("Cloning with strategy", Some(&strategy));
+    if let Some(source) = source {
+        pretty_print("Cloning from", Some(&source));
+    }
 
-    let tasks: Vec<_> = repositories
+    let tasks = repositories
         .iter()
         .map(|repository| {
-            let semaphore_clone = semaphore.clone();
+            let task = clone_repo(&repos_path, repository, source, strategy);
+            (repo_folder_name(repository), task)
+        })
+        .collect();
+    let report = run_clones(tasks).await;
+    report.print_summary();
 
-            async move {
-                let _permit = semaphore_clone
-                    .acquire()
-                    .await
-                    .unwrap_or_else(|_| panic!("Failed to acquire permit"));
+    report.save(workspace)?;
+    pin_repos(&repos_path, repositories, &report)
+        .await
+        .save(workspace)?;
+    state.complete(workspace, Stage::Clone);
+    state.save(workspace)?;
+    Ok(report)
+}
 
-                clone_repo(repository).await
-            }
+async fn restore_repo(
+    repos_path: &Path,
+    pinned: &PinnedRepo,
+    source: Option<&str>,
+    strategy: CloneStrategy,
+) -> Result<CloneStatus, Box<dyn Error + Send + Sync>> {
+    let path = repos_path.join(pinned.repo_path());
+    if !path.exists() {
+        clone_repo(repos_path, &pinned.repository, source, strategy).await?;
+    }
+    checkout_revision(&path, &pinned.revision).await?;
+    Ok(CloneStatus::Ok)
+}
+
+/// Clones the repositories of a manifest at their recorded commits, replacing the
+/// fetched and cloned repositories of the dataset
+pub async fn restore_repos(
+    workspace: &Workspace,
+    state: &mut ScraperState,
+    manifest: CloneManifest,
+) -> Result<(), Box<dyn Error>> {
+    let repositories: Vec<Repository> = manifest
+        .0
+        .iter()
+        .map(|pinned| pinned.repository.clone())
+        .collect();
+    PopularRepos(repositories).save(workspace)?;
+    state.complete(workspace, Stage::Fetch);
+    state.save(workspace)?;
+
+    let repos_path = workspace.repos_path();
+    let source = state.clone_source.as_deref();
+    let tasks = manifest
+        .0
+        .iter()
+        .map(|pinned| {
+            let task = restore_repo(&repos_path, pinned, source, state.clone_strategy);
+            (pinned.repo_path(), task)
         })
         .collect();
+    let report = run_clones(tasks).await;
+    report.print_summary();
+
+    report.save(workspace)?;
+    let restored = manifest
+        .0
+        .into_iter()
+        .filter(|pinned| report.is_cloned(&pinned.repo_path()))
+        .collect();
+    CloneManifest(restored).save(workspace)?;
+    state.complete(workspace, Stage::Clone);
+    state.save(workspace)?;
+    pretty_print("Repositories restored", None);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_github::spawn_mock_github;
+    use serde_json::{json, Value};
+    use std::{fs, path::PathBuf};
+
+    fn repository(index: usize, stars: i64) -> Value {
+        json!({
+            "__typename": "Repository",
+            "nameWithOwner": format!("owner/repo-{}", index),
+            "name": format!("repo-{}", index),
+            "id": format!("R_{}", index),
+            "owner": { "__typename": "User", "login": "owner" },
+            "url": format!("https://github.com/owner/repo-{}", index),
+            "stargazers": { "totalCount": stars },
+        })
+    }
+
+    fn page(nodes: Vec<Value>, end_cursor: &str, has_next_page: bool) -> Value {
+        json!({
+            "status": 200,
+            "body": { "data": { "search": {
+                "repositoryCount": nodes.len(),
+                "pageInfo": { "hasNextPage": has_next_page, "endCursor": end_cursor },
+                "nodes": nodes,
+            } } },
+        })
+    }
+
+    // Client of a responder replaying the fixtures, with the variables of the requests it received
+    async fn client(fixtures_dir: &Path) -> (GithubClient, impl Fn() -> Vec<Value>) {
+        let (url, received) = spawn_mock_github(fixtures_dir).await.unwrap();
+        let client = GithubClient::new(&Workspace::default().with_graphql_url(url)).unwrap();
+        let requests = move || {
+            let requests = received.lock().unwrap();
+            requests
+                .iter()
+                .map(|body| body["variables"].clone())
+                .collect()
+        };
+        (client, requests)
+    }
+
+    #[tokio::test]
+    async fn follows_the_end_cursor() {
+        let (github, requests) = client(Path::new("fixtures/github/pagination")).await;
+        let mut repos = Vec::new();
+        let selection = RepoSelection::default();
+        fetch_star_ranges(
+            &github,
+            &selection,
+            "",
+            100,
+            &mut repos,
+            &mut HashSet::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(repos.len(), 6);
+        let requests = requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[0]["qstr"],
+            "language:Rust stars:>=1 sort:stars-desc"
+        );
+        assert_eq!(requests[0]["after"], Value::Null);
+        assert_eq!(requests[1]["after"], "Y3Vyc29yOjM=");
+    }
+
+    #[tokio::test]
+    async fn splits_the_star_range_past_the_search_limit() {
+        let fixtures_dir = tempfile::tempdir().unwrap();
+        let pages = MAX_SEARCH_RESULTS / PAGE_SIZE as usize;
+        for index in 0..pages {
+            let nodes = (0..PAGE_SIZE as usize)
+                .map(|i| index * PAGE_SIZE as usize + i)
+                .map(|i| repository(i, 5000 - i as i64))
+                .collect();
+            let cursor = format!("cursor-{}", index);
+            let fixture = page(nodes, &cursor, true);
+            let path = fixtures_dir.path().join(format!("{:02}.json", index));
+            fs::write(path, fixture.to_string()).unwrap();
+        }
+        // The next range starts again at the least starred repository, already fetched
+        let last = MAX_SEARCH_RESULTS - 1;
+        let nodes = vec![
+            repository(last, 5000 - last as i64),
+            repository(last + 1, 100),
+        ];
+        let fixture = page(nodes, "cursor-last", false);
+        fs::write(fixtures_dir.path().join("99.json"), fixture.to_string()).unwrap();
+
+        let (github, requests) = client(fixtures_dir.path()).await;
+        let mut repos = Vec::new();
+        let selection = RepoSelection::default();
+        fetch_star_ranges(
+            &github,
+            &selection,
+            "",
+            2000,
+            &mut repos,
+            &mut HashSet::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(repos.len(), MAX_SEARCH_RESULTS + 1);
+        let requests = requests();
+        assert_eq!(requests.len(), pages + 1);
+        assert_eq!(requests[1]["after"], "cursor-0");
+        assert_eq!(
+            requests[pages - 1]["after"],
+            format!("cursor-{}", pages - 2)
+        );
+        let min_stars = 5000 - last as i64;
+        let qstr = format!("language:Rust stars:1..{} sort:stars-desc", min_stars);
+        assert_eq!(requests[pages]["qstr"], qstr);
+        assert_eq!(requests[pages]["after"], Value::Null);
+    }
+
+    #[tokio::test]
+    async fn retries_rate_limited_requests() {
+        let (github, requests) = client(Path::new("fixtures/github/rate-limit")).await;
+        let data = github.search_page("language:Rust", None).await.unwrap();
+        let repos: Vec<Repository> = data.try_into().unwrap();
+
+        assert_eq!(repos.len(), 3);
+        assert_eq!(requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_bad_credentials() {
+        let (github, requests) = client(Path::new("fixtures/github/bad-credentials")).await;
+        let result = github.search_page("language:Rust", None).await;
+
+        assert!(
+            matches!(result, Err(GithubError::Unauthorized(message)) if message == "Bad credentials")
+        );
+        assert_eq!(requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn retries_rate_limits_reported_as_graphql_errors() {
+        let fixtures_dir = Path::new("fixtures/github/graphql-rate-limit");
+        let (github, _) = client(fixtures_dir).await;
+        let result = github.try_search_page("language:Rust", None).await;
+        assert!(matches!(
+            result,
+            Err(GithubError::RateLimited { message, .. }) if message.starts_with("API rate limit exceeded")
+        ));
+
+        let (github, requests) = client(fixtures_dir).await;
+        let data = github.search_page("language:Rust", None).await.unwrap();
+        let repos: Vec<Repository> = data.try_into().unwrap();
+        assert_eq!(repos.len(), 3);
+        assert_eq!(requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_graphql_errors() {
+        let (github, requests) = client(Path::new("fixtures/github/graphql-errors")).await;
+        let result = github.search_page("language:Rust", None).await;
+
+        let Err(GithubError::GraphQL(messages)) = result else {
+            panic!("Expected GraphQL errors, got {:?}", result);
+        };
+        assert_eq!(
+            messages,
+            ["`invalid` does not appear to be a valid cursor."]
+        );
+        assert_eq!(requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_missing_data() {
+        let (github, requests) = client(Path::new("fixtures/github/data-null")).await;
+        let result = github.search_page("language:Rust", None).await;
+
+        assert!(matches!(result, Err(GithubError::MissingData)));
+        assert_eq!(requests().len(), 1);
+    }
+
+    #[test]
+    fn keeps_only_repository_nodes() {
+        let data: repos_query::ResponseData = serde_json::from_value(json!({ "search": {
+            "repositoryCount": 3,
+            "pageInfo": { "hasNextPage": false, "endCursor": null },
+            "nodes": [repository(0, 10), { "__typename": "User" }, null],
+        } }))
+        .unwrap();
+        let repos: Vec<Repository> = data.try_into().unwrap();
+
+        assert_eq!(repos.len(), 1);
+        assert_eq!(repos[0].name_with_owner, "owner/repo-0");
+    }
+
+    #[test]
+    fn fails_without_nodes() {
+        let data: repos_query::ResponseData = serde_json::from_value(json!({ "search": {
+            "repositoryCount": 0,
+            "pageInfo": { "hasNextPage": false, "endCursor": null },
+            "nodes": null,
+        } }))
+        .unwrap();
+        let result: Result<Vec<Repository>, _> = data.try_into();
+
+        assert!(matches!(result, Err(GithubError::MissingNodes)));
+    }
+
+    fn run(dir: &Path, args: &[&str]) {
+        let status = std::process::Command::new(args[0])
+            .args(&args[1..])
+            .current_dir(dir)
+            .status()
+            .unwrap();
+        assert!(status.success(), "{:?} failed", args);
+    }
+
+    fn commit(checkout: &Path, content: &str) {
+        fs::write(checkout.join("src/lib.rs"), content).unwrap();
+        run(checkout, &["git", "add", "-A"]);
+        let identity = ["-c", "user.name=test", "-c", "user.email=test@example.com"];
+        run(
+            checkout,
+            &[&["git"], &identity[..], &["commit", "-qm", "commit"]].concat(),
+        );
+    }
 
-    for task in tasks {
-        if let Err(err) = task.await {
-            println!("Failed to clone: {}", err)
+    fn pack(corpus: &Path, content: &str) {
+        let unpacked = corpus.join("repo-1-main");
+        fs::create_dir_all(unpacked.join("src")).unwrap();
+        fs::write(
+            unpacked.join("Cargo.toml"),
+            "[package]\nname = \"repo-1\"\n",
+        )
+        .unwrap();
+        fs::write(unpacked.join("src/lib.rs"), content).unwrap();
+        run(
+            corpus,
+            &["tar", "-czf", "owner.repo-1.tar.gz", "repo-1-main"],
+        );
+        fs::remove_dir_all(unpacked).unwrap();
+    }
+
+    // Source directory with an `owner.repo-0` checkout and an `owner.repo-1` archive
+    fn fixture_corpus() -> tempfile::TempDir {
+        let corpus = tempfile::tempdir().unwrap();
+        let checkout = corpus.path().join("owner.repo-0");
+        fs::create_dir_all(checkout.join("src")).unwrap();
+        fs::write(
+            checkout.join("Cargo.toml"),
+            "[package]\nname = \"repo-0\"\n",
+        )
+        .unwrap();
+        run(&checkout, &["git", "init", "-q", "-b", "main"]);
+        commit(&checkout, "#[derive(Debug)]\nstruct A;\n");
+        pack(corpus.path(), "#[derive(Debug)]\nstruct B;\n");
+        corpus
+    }
+
+    // The path relative to the working directory, as typed on the command line
+    fn relative_to_cwd(path: &Path) -> String {
+        let cwd = std::env::current_dir().unwrap();
+        let mut relative = PathBuf::new();
+        for _ in cwd.components().skip(1) {
+            relative.push("..");
         }
+        let absolute = path.canonicalize().unwrap();
+        relative
+            .join(absolute.strip_prefix("/").unwrap())
+            .to_string_lossy()
+            .to_string()
     }
 
-    state.cloned_repos_at = Some(Local::now());
-    state.save()?;
-    pretty_print("Repositories cloned", None);
-    Ok(CLONED_REPOS_PATH.to_string())
+    #[tokio::test]
+    async fn updates_checkouts_from_a_relative_source() {
+        let corpus = fixture_corpus();
+        let repos = tempfile::tempdir().unwrap();
+        let repository: Repository = serde_json::from_value(repository(0, 1)).unwrap();
+        let source = relative_to_cwd(corpus.path());
+        let clone = || {
+            clone_repo(
+                repos.path(),
+                &repository,
+                Some(&source),
+                CloneStrategy::Full,
+            )
+        };
+
+        assert_eq!(clone().await.unwrap(), CloneStatus::Ok);
+        let path = repos.path().join("owner.repo-0");
+        assert!(path.join("src/lib.rs").exists());
+        assert_eq!(clone().await.unwrap(), CloneStatus::Skipped);
+
+        // The remote has to keep working from the repository folder
+        let checkout = corpus.path().join("owner.repo-0");
+        commit(&checkout, "#[derive(Clone)]\nstruct A;\n");
+        assert_eq!(clone().await.unwrap(), CloneStatus::Ok);
+        let content = fs::read_to_string(path.join("src/lib.rs")).unwrap();
+        assert!(content.contains("Clone"));
+        let origin = git(&path, &["remote", "get-url", "origin"]).await.unwrap();
+        assert_eq!(Path::new(&origin), checkout.canonicalize().unwrap());
+    }
+
+    #[tokio::test]
+    async fn unpacks_archives_once_until_they_change() {
+        let corpus = fixture_corpus();
+        let repos = tempfile::tempdir().unwrap();
+        let repository: Repository = serde_json::from_value(repository(1, 1)).unwrap();
+        let source = corpus.path().to_string_lossy().to_string();
+        let clone = || {
+            clone_repo(
+                repos.path(),
+                &repository,
+                Some(&source),
+                CloneStrategy::Full,
+            )
+        };
+
+        assert_eq!(clone().await.unwrap(), CloneStatus::Ok);
+        let path = repos.path().join("owner.repo-1");
+        assert!(path.join("Cargo.toml").exists());
+        assert_eq!(clone().await.unwrap(), CloneStatus::Skipped);
+
+        pack(corpus.path(), "#[derive(Clone, Debug)]\nstruct B;\n");
+        assert_eq!(clone().await.unwrap(), CloneStatus::Ok);
+        let content = fs::read_to_string(path.join("src/lib.rs")).unwrap();
+        assert!(content.contains("Clone"));
+    }
 }
@@ -0,0 +1,244 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use glob::{MatchOptions, Pattern};
+
+use crate::{crate_paths::get_repo_path, workspace::Workspace};
+
+/// Name of the ignore files, in the data directory and at the root of each repository
+pub const IGNORE_FILE_NAME: &str = ".macroanalyzerignore";
+
+// Build output, sources the tree-sitter grammar can't parse and the files written by `expand`
+const DEFAULT_IGNORE_PATTERNS: [&str; 4] = [
+    "target/",
+    "malformed/",
+    "package_loader.rs",
+    ".macro-expanded.rs",
+];
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+#[derive(Debug)]
+struct IgnoreRule {
+    pattern: Pattern,
+    negated: bool,
+    dir_only: bool,
+    // Patterns with a `/` match the path from the root, others match the name at any depth
+    anchored: bool,
+}
+
+impl IgnoreRule {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(line) => (true, line),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.trim_start_matches('/');
+        match Pattern::new(line) {
+            Ok(pattern) => Some(Self {
+                pattern,
+                negated,
+                dir_only,
+                anchored,
+            }),
+            Err(e) => {
+                println!("Invalid ignore pattern {:?}: {}", line, e);
+                None
+            }
+        }
+    }
+
+    fn matches(&self, relative: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        match self.anchored {
+            true => self.pattern.matches_with(relative, MATCH_OPTIONS),
+            false => {
+                let name = relative.rsplit('/').next().unwrap_or(relative);
+                self.pattern.matches_with(name, MATCH_OPTIONS)
+            }
+        }
+    }
+}
+
+/// Gitignore-style patterns excluding files and directories of a repository from
+/// every stage reading its sources
+#[derive(Debug)]
+pub struct IgnoreRules {
+    root: PathBuf,
+    rules: Vec<IgnoreRule>,
+}
+
+impl IgnoreRules {
+    /// Default patterns, followed by the ignore file of the data directory and the
+    /// one of the repository, so later files can re-include paths with `!`
+    pub fn for_repo(workspace: &Workspace, repo_dir: &Path) -> Self {
+        let mut rules: Vec<IgnoreRule> = DEFAULT_IGNORE_PATTERNS
+            .iter()
+            .filter_map(|line| IgnoreRule::parse(line))
+            .collect();
+        for ignore_file in [workspace.ignore_path(), repo_dir.join(IGNORE_FILE_NAME)] {
+            if let Ok(content) = fs::read_to_string(ignore_file) {
+                rules.extend(content.lines().filter_map(IgnoreRule::parse));
+            }
+        }
+        Self {
+            root: repo_dir.to_path_buf(),
+            rules,
+        }
+    }
+
+    /// Rules of the repository the crate belongs to
+    pub fn for_crate(workspace: &Workspace, crate_path: &str) -> Self {
+        Self::for_repo(workspace, &workspace.repo_dir(&get_repo_path(crate_path)))
+    }
+
+    // The last matching rule wins
+    fn matches(&self, relative: &str, is_dir: bool) -> bool {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.matches(relative, is_dir))
+            .is_some_and(|rule| !rule.negated)
+    }
+
+    /// Whether the path or one of its parent directories below the root is excluded
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        let components: Vec<String> = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().to_string())
+            .collect();
+        (1..=components.len()).any(|depth| {
+            let is_dir = depth < components.len() || path.is_dir();
+            self.matches(&components[..depth].join("/"), is_dir)
+        })
+    }
+}
+
+/// Collects the `.rs` files under the directory, leaving out the excluded files and
+/// directories, which are added to `ignored`. Nested crates, whose directory has its
+/// own manifest, are left to their own analysis.
+pub fn collect_rust_files(
+    dir: &Path,
+    rules: &IgnoreRules,
+    files: &mut Vec<PathBuf>,
+    ignored: &mut Vec<PathBuf>,
+) -> std::io::Result<()> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    entries.sort();
+    for path in entries {
+        let is_rust_file = path.extension() == Some(std::ffi::OsStr::new("rs")) && path.is_file();
+        if !is_rust_file && (!path.is_dir() || path.join("Cargo.toml").is_file()) {
+            continue;
+        }
+        if rules.is_ignored(&path) {
+            ignored.push(path);
+        } else if is_rust_file {
+            files.push(path);
+        } else {
+            collect_rust_files(&path, rules, files, ignored)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Rules of the lines alone, for a repository in a temporary directory
+    fn rules(lines: &[&str]) -> (tempfile::TempDir, IgnoreRules) {
+        let root = tempfile::tempdir().unwrap();
+        let rules = IgnoreRules {
+            root: root.path().to_path_buf(),
+            rules: lines
+                .iter()
+                .filter_map(|line| IgnoreRule::parse(line))
+                .collect(),
+        };
+        (root, rules)
+    }
+
+    #[test]
+    fn anchors_patterns_with_a_slash_to_the_root() {
+        let (root, rules) = rules(&["/build.rs", "docs/*.rs", "generated.rs"]);
+        let is_ignored = |path: &str| rules.is_ignored(&root.path().join(path));
+
+        assert!(is_ignored("build.rs"));
+        assert!(!is_ignored("src/build.rs"));
+        assert!(is_ignored("docs/a.rs"));
+        assert!(!is_ignored("src/docs/a.rs"));
+        assert!(!is_ignored("docs/nested/a.rs"));
+        assert!(is_ignored("generated.rs"));
+        assert!(is_ignored("src/deep/generated.rs"));
+        assert!(!rules.is_ignored(Path::new("/elsewhere/generated.rs")));
+    }
+
+    #[test]
+    fn matches_directory_patterns_on_directories_and_their_files() {
+        let (root, rules) = rules(&["out/"]);
+        fs::create_dir_all(root.path().join("src/out")).unwrap();
+        fs::write(root.path().join("out"), "").unwrap();
+        let is_ignored = |path: &str| rules.is_ignored(&root.path().join(path));
+
+        assert!(is_ignored("src/out"));
+        assert!(is_ignored("src/out/a.rs"));
+        assert!(is_ignored("src/out/nested/a.rs"));
+        // A file of the same name
+        assert!(!is_ignored("out"));
+    }
+
+    #[test]
+    fn lets_the_last_matching_rule_win() {
+        let (root, rules) = rules(&["gen_*.rs", "!gen_keep.rs", "!gen_old.rs", "gen_old.rs"]);
+        let is_ignored = |path: &str| rules.is_ignored(&root.path().join(path));
+
+        assert!(is_ignored("src/gen_a.rs"));
+        assert!(!is_ignored("src/gen_keep.rs"));
+        assert!(is_ignored("src/gen_old.rs"));
+    }
+
+    #[test]
+    fn layers_the_repository_file_over_the_data_directory_one() {
+        let root = tempfile::tempdir().unwrap();
+        let workspace = Workspace::new(root.path());
+        let repo_dir = workspace.repo_dir("repo");
+        fs::create_dir_all(&repo_dir).unwrap();
+        fs::write(workspace.ignore_path(), "fixtures/\nbench_*.rs\n").unwrap();
+        fs::write(
+            repo_dir.join(IGNORE_FILE_NAME),
+            "# Sources checked in the build output\n!target/\n!bench_keep.rs\n",
+        )
+        .unwrap();
+        let rules = IgnoreRules::for_repo(&workspace, &repo_dir);
+        let is_ignored = |path: &str| rules.is_ignored(&repo_dir.join(path));
+
+        assert!(is_ignored("fixtures/a.rs"));
+        assert!(is_ignored("src/bench_a.rs"));
+        assert!(!is_ignored("src/bench_keep.rs"));
+        assert!(!is_ignored("target/generated.rs"));
+        assert!(is_ignored("malformed/a.rs"));
+        assert!(!is_ignored("src/lib.rs"));
+    }
+}
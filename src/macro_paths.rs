@@ -0,0 +1,356 @@
+use std::collections::{HashMap, HashSet};
+
+use tree_sitter::Node;
+
+// Macros exported at the root of `std`, in scope everywhere through the prelude
+const STD_MACROS: [&str; 40] = [
+    "assert",
+    "assert_eq",
+    "assert_ne",
+    "cfg",
+    "column",
+    "compile_error",
+    "concat",
+    "dbg",
+    "debug_assert",
+    "debug_assert_eq",
+    "debug_assert_ne",
+    "env",
+    "eprint",
+    "eprintln",
+    "file",
+    "format",
+    "format_args",
+    "include",
+    "include_bytes",
+    "include_str",
+    "is_x86_feature_detected",
+    "line",
+    "matches",
+    "module_path",
+    "option_env",
+    "panic",
+    "print",
+    "println",
+    "stringify",
+    "thread_local",
+    "todo",
+    "try",
+    "unimplemented",
+    "unreachable",
+    "vec",
+    "write",
+    "writeln",
+    "concat_idents",
+    "trace_macros",
+    "log_syntax",
+];
+
+// Derive macros of the standard library prelude
+const STD_DERIVES: [(&str, &str); 9] = [
+    ("Clone", "core::clone::Clone"),
+    ("Copy", "core::marker::Copy"),
+    ("Debug", "core::fmt::Debug"),
+    ("Default", "core::default::Default"),
+    ("Eq", "core::cmp::Eq"),
+    ("Hash", "core::hash::Hash"),
+    ("Ord", "core::cmp::Ord"),
+    ("PartialEq", "core::cmp::PartialEq"),
+    ("PartialOrd", "core::cmp::PartialOrd"),
+];
+
+/// Path prefixes of the items of the current crate
+pub const LOCAL_PREFIXES: [&str; 4] = ["crate", "self", "super", "$crate"];
+
+fn node_text(node: Node, bytes: &[u8]) -> String {
+    String::from_utf8_lossy(&bytes[node.byte_range()])
+        .split_whitespace()
+        .collect()
+}
+
+fn join_path(prefix: &str, path: &str) -> String {
+    let path = path.trim_start_matches("::");
+    match prefix.is_empty() {
+        true => path.to_string(),
+        false => format!("{}::{}", prefix, path),
+    }
+}
+
+// Rewrites the leading `self`, `super`, `crate` and `$crate` segments of a path to
+// `crate`, since modules aren't told apart
+fn normalize_local(path: &str) -> String {
+    let segments: Vec<&str> = path.split("::").collect();
+    let local = segments
+        .iter()
+        .take_while(|segment| LOCAL_PREFIXES.contains(segment))
+        .count();
+    match local {
+        0 => path.to_string(),
+        _ => std::iter::once("crate")
+            .chain(segments[local..].iter().copied())
+            .collect::<Vec<_>>()
+            .join("::"),
+    }
+}
+
+/// Names brought into scope in a file by its `use` declarations, `extern crate` items
+/// and `macro_rules!` definitions, used to find the canonical path of the macros it uses
+#[derive(Default, Debug)]
+pub struct MacroScope {
+    // Imported name or alias, with the path it refers to
+    imports: HashMap<String, String>,
+    // Paths of the `use <path>::*` declarations
+    globs: Vec<String>,
+    // Crates of the `#[macro_use] extern crate` items
+    macro_use_crates: Vec<String>,
+    local_macros: HashSet<String>,
+}
+
+impl MacroScope {
+    /// Collects the declarations of the whole file, without telling modules apart
+    pub fn from_tree(root: Node, bytes: &[u8]) -> Self {
+        let mut scope = Self::default();
+        scope.add_declarations(root, bytes);
+        scope
+    }
+
+    fn add_declarations(&mut self, node: Node, bytes: &[u8]) {
+        for child in node.children(&mut node.walk()) {
+            match child.kind() {
+                "use_declaration" => {
+                    if let Some(argument) = child.child_by_field_name("argument") {
+                        self.add_use(argument, bytes, "");
+                    }
+                }
+                "extern_crate_declaration" => self.add_extern_crate(child, bytes),
+                "macro_definition" => {
+                    if let Some(name) = child.child_by_field_name("name") {
+                        self.local_macros.insert(node_text(name, bytes));
+                    }
+                }
+                _ if child.child_count() > 0 => self.add_declarations(child, bytes),
+                _ => (),
+            }
+        }
+    }
+
+    fn add_use(&mut self, node: Node, bytes: &[u8], prefix: &str) {
+        match node.kind() {
+            "use_as_clause" => {
+                let (Some(path), Some(alias)) = (
+                    node.child_by_field_name("path"),
+                    node.child_by_field_name("alias"),
+                ) else {
+                    return;
+                };
+                let alias = node_text(alias, bytes);
+                let path = match path.kind() {
+                    "self" => prefix.to_string(),
+                    _ => join_path(prefix, &node_text(path, bytes)),
+                };
+                if alias != "_" {
+                    self.imports.insert(alias, normalize_local(&path));
+                }
+            }
+            "scoped_use_list" => {
+                let prefix = match node.child_by_field_name("path") {
+                    Some(path) => join_path(prefix, &node_text(path, bytes)),
+                    None => prefix.to_string(),
+                };
+                if let Some(list) = node.child_by_field_name("list") {
+                    self.add_use(list, bytes, &prefix);
+                }
+            }
+            "use_list" => {
+                for item in node.named_children(&mut node.walk()) {
+                    self.add_use(item, bytes, prefix);
+                }
+            }
+            "use_wildcard" => {
+                let path = node_text(node, bytes);
+                let path = path.trim_end_matches('*').trim_end_matches("::");
+                self.globs.push(normalize_local(&join_path(prefix, path)));
+            }
+            // `use serde::{self}` imports `serde` itself
+            "self" => {
+                if let Some(name) = prefix.rsplit("::").next() {
+                    self.imports
+                        .insert(name.to_string(), normalize_local(prefix));
+                }
+            }
+            "identifier" | "scoped_identifier" => {
+                let path = join_path(prefix, &node_text(node, bytes));
+                if let Some(name) = path.rsplit("::").next() {
+                    self.imports
+                        .insert(name.to_string(), normalize_local(&path));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn add_extern_crate(&mut self, node: Node, bytes: &[u8]) {
+        let Some(name) = node.child_by_field_name("name") else {
+            return;
+        };
+        let name = node_text(name, bytes);
+        if let Some(alias) = node.child_by_field_name("alias") {
+            self.imports.insert(node_text(alias, bytes), name.clone());
+        }
+        let mut prev = node.prev_sibling();
+        while let Some(attribute_item) = prev.filter(|n| n.kind() == "attribute_item") {
+            prev = attribute_item.prev_sibling();
+            let is_macro_use = attribute_item
+                .child(2)
+                .and_then(|attribute| attribute.child(0))
+                .is_some_and(|identifier| node_text(identifier, bytes) == "macro_use");
+            if is_macro_use {
+                self.macro_use_crates.push(name.clone());
+            }
+        }
+    }
+
+    // Resolves the first segment of the path through the imports
+    fn resolve_path(&self, spelling: &str) -> String {
+        let spelling = spelling.trim_start_matches("::");
+        let (first, rest) = spelling.split_once("::").unwrap_or((spelling, ""));
+        if LOCAL_PREFIXES.contains(&first) {
+            return normalize_local(spelling);
+        }
+        let first = self.imports.get(first).map_or(first, |path| path.as_str());
+        join_path(first, rest)
+    }
+
+    // Path of a single name: imports shadow the preludes, while globs and
+    // `#[macro_use]` crates are only followed when there is a single candidate
+    fn resolve(&self, spelling: &str, prelude: Option<String>) -> String {
+        if spelling.contains("::") {
+            return self.resolve_path(spelling);
+        }
+        if let Some(path) = self.imports.get(spelling) {
+            return path.clone();
+        }
+        if self.local_macros.contains(spelling) {
+            return join_path("crate", spelling);
+        }
+        if let Some(path) = prelude {
+            return path;
+        }
+        let mut candidates = self.globs.iter().chain(&self.macro_use_crates);
+        match (candidates.next(), candidates.next()) {
+            (Some(prefix), None) => join_path(prefix, spelling),
+            _ => spelling.to_string(),
+        }
+    }
+
+    /// Canonical path of a function-like macro, e.g. `std::println` or `tracing::info`
+    pub fn resolve_macro(&self, spelling: &str) -> String {
+        let prelude = STD_MACROS
+            .contains(&spelling)
+            .then(|| join_path("std", spelling));
+        self.resolve(spelling, prelude)
+    }
+
+    /// Canonical path of a derive macro, e.g. `core::fmt::Debug` or `serde::Serialize`
+    pub fn resolve_derive(&self, spelling: &str) -> String {
+        let prelude = STD_DERIVES
+            .iter()
+            .find(|(name, _)| *name == spelling)
+            .map(|(_, path)| path.to_string());
+        let path = self.resolve(spelling, prelude);
+        // `std` re-exports the derives of `core`
+        match path.strip_prefix("std::") {
+            Some(rest)
+                if STD_DERIVES
+                    .iter()
+                    .any(|(_, core_path)| core_path.strip_prefix("core::") == Some(rest)) =>
+            {
+                join_path("core", rest)
+            }
+            _ => path,
+        }
+    }
+
+    /// Canonical path of an attribute macro, e.g. `tokio::main`
+    pub fn resolve_attribute(&self, spelling: &str) -> String {
+        self.resolve(spelling, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::parse_file;
+
+    fn scope(source: &str) -> MacroScope {
+        let tree = parse_file(source.as_bytes());
+        MacroScope::from_tree(tree.root_node(), source.as_bytes())
+    }
+
+    #[test]
+    fn resolves_imports_and_their_aliases() {
+        let scope = scope(
+            r#"
+            use tracing::{self as tr, info as log_info};
+            use serde::Serialize as Ser;
+            use crate::reexports::{self};
+            use self::helpers::local;
+            use super::super::parent::{other, Derived as D};
+            use ::anyhow::bail;
+            use quote::quote as _;
+            "#,
+        );
+        assert_eq!(scope.resolve_macro("log_info"), "tracing::info");
+        assert_eq!(scope.resolve_macro("tr::warn"), "tracing::warn");
+        assert_eq!(scope.resolve_derive("Ser"), "serde::Serialize");
+        assert_eq!(scope.resolve_macro("reexports::m"), "crate::reexports::m");
+        assert_eq!(scope.resolve_macro("local"), "crate::helpers::local");
+        assert_eq!(scope.resolve_macro("other"), "crate::parent::other");
+        assert_eq!(scope.resolve_derive("D"), "crate::parent::Derived");
+        assert_eq!(scope.resolve_macro("bail"), "anyhow::bail");
+        assert_eq!(scope.resolve_macro("quote"), "quote");
+        assert_eq!(scope.resolve_macro("self::m"), "crate::m");
+        assert_eq!(scope.resolve_macro("$crate::m"), "crate::m");
+    }
+
+    #[test]
+    fn follows_a_single_glob_or_macro_use_crate() {
+        let glob = scope("use tokio::*;");
+        assert_eq!(glob.resolve_attribute("main"), "tokio::main");
+        let local_glob = scope("use super::macros::*;");
+        assert_eq!(local_glob.resolve_macro("m"), "crate::macros::m");
+        let macro_use = scope("#[macro_use]\nextern crate log;");
+        assert_eq!(macro_use.resolve_macro("info"), "log::info");
+        let renamed = scope("extern crate serde_json as json;");
+        assert_eq!(renamed.resolve_macro("json::json"), "serde_json::json");
+
+        let ambiguous = scope("use tokio::*;\n#[macro_use]\nextern crate log;");
+        assert_eq!(ambiguous.resolve_macro("info"), "info");
+    }
+
+    #[test]
+    fn lets_imports_and_local_macros_shadow_the_prelude() {
+        assert_eq!(scope("").resolve_macro("println"), "std::println");
+        let imported = scope("use defmt::println;");
+        assert_eq!(imported.resolve_macro("println"), "defmt::println");
+        let local = scope("macro_rules! vec { () => {}; }");
+        assert_eq!(local.resolve_macro("vec"), "crate::vec");
+        // Globs don't shadow the prelude
+        let glob = scope("use serde::*;");
+        assert_eq!(glob.resolve_derive("Clone"), "core::clone::Clone");
+        assert_eq!(glob.resolve_derive("Serialize"), "serde::Serialize");
+    }
+
+    #[test]
+    fn folds_std_derives_into_core() {
+        let scope = scope("use std::fmt::Debug as D;\nuse std::hash;");
+        assert_eq!(scope.resolve_derive("Debug"), "core::fmt::Debug");
+        assert_eq!(scope.resolve_derive("D"), "core::fmt::Debug");
+        assert_eq!(scope.resolve_derive("hash::Hash"), "core::hash::Hash");
+        assert_eq!(scope.resolve_derive("std::cmp::Ord"), "core::cmp::Ord");
+        assert_eq!(
+            scope.resolve_derive("std::fmt::Display"),
+            "std::fmt::Display"
+        );
+    }
+}
@@ -1,67 +1,84 @@
-use analyzis::analyze_crates;
-use analyzis::calculate_overall;
-use clear_cfg::parse_code;
-use count_code::{count_crates_code, count_expanded_code};
-use crate_paths::find_crate_paths;
-use data::Data;
-use expand::expand_crates;
-use github::clone_repos;
-use github::get_most_popular_repos;
-use http::start_server;
-use results::AnalyzisResults;
+use clap::Parser;
+use cli::{Cli, Command, PipelineCommand, GITHUB_SOURCE};
+use mock_github::start_mock_github;
+use pipeline::run_command;
 use state::ScraperState;
 use std::error::Error;
-use std::path::Path;
-use utils::create_data_folder;
+use workspace::Workspace;
 
 #[macro_use]
 mod utils;
 mod analyzis;
 mod cargo;
 mod clear_cfg;
+mod cli;
 mod count_code;
 mod crate_paths;
+mod crates_io;
 mod data;
 mod error;
 mod expand;
+mod git;
 mod github;
 mod http;
+mod ignore;
+mod macro_paths;
+mod mock_github;
+mod module_tree;
+mod pipeline;
+mod providers;
 mod results;
+mod source;
+mod source_class;
 mod state;
+mod targets;
+mod workspace;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    create_data_folder();
-    let mut state = ScraperState::load().unwrap_or_default();
-    let repos = get_most_popular_repos(&mut state).await?;
-    let repos_path = clone_repos(&mut state, &repos).await?;
-    let crate_paths = find_crate_paths(&mut state, Path::new(&repos_path))?;
-    let mut results =
-        AnalyzisResults::load().unwrap_or(AnalyzisResults::from((&crate_paths, &repos)));
-    results.save()?;
-    // Without expanded:
-    analyze_crates(&mut state, &mut results)?;
-    // remove whitespace
-    // parse_code(&mut state, &crate_paths)?;
-    count_crates_code(&mut state, &mut results)?;
-
-    // With expanded:
-    // analyze_crates(&mut state, &mut results)?;
-    // clear_conditional_compilation(&mut state, &crate_paths)?;
-    // count_crates_code(&mut state, &mut results)?;
-    // expand_crates(&mut state, &mut results).await?;
-    // count_expanded_code(&mut state, &mut results)?;
-
-    let mut data: Data = results.clone().into();
-    data.date = state
-        .cloned_repos_at
-        .expect("Repositories should have been cloned by now");
-    let serialized = serde_json::to_string(&data)?;
-    let mut file = std::fs::File::create("data/data.json")?;
-    std::io::Write::write_all(&mut file, serialized.as_bytes())?;
-    results.save()?;
-    state.save()?;
-
-    start_server(data).await?;
-    Ok(())
+    let cli = Cli::parse();
+    let command = match cli.command.unwrap_or_default() {
+        Command::Pipeline(command) => command,
+        // The responder needs no dataset, so it runs before the workspace is created
+        Command::MockGithub { fixtures, port } => {
+            return start_mock_github(&fixtures, port).await;
+        }
+    };
+    let mut workspace = Workspace::new(cli.data_dir);
+    if let Some(graphql_url) = cli.graphql_url {
+        workspace = workspace.with_graphql_url(graphql_url);
+    }
+    if let PipelineCommand::Local { dirs, .. } = &command {
+        workspace = workspace.with_local_repos(dirs)?;
+        // The crates found would replace the ones of the fetched repositories
+        if workspace.popular_repos_path().exists() {
+            return Err(format!(
+                "{:?} holds fetched repositories, pass another --data-dir to analyze local directories",
+                workspace.root()
+            )
+            .into());
+        }
+    }
+    workspace.create_folder()?;
+    let mut state = ScraperState::load(&workspace).unwrap_or_default();
+    let selection = cli.selection.apply(&state.repos_selection)?;
+    state.select_repos(&workspace, selection);
+    if let Some(strategy) = cli.clone_strategy {
+        state.select_clone_strategy(&workspace, strategy);
+    }
+    if let Some(discovery) = cli.discovery {
+        state.select_discovery(&workspace, discovery);
+    }
+    if let Some(collection) = cli.file_collection {
+        state.select_file_collection(&workspace, collection);
+    }
+    if let Some(source) = cli.source {
+        let source = (source != GITHUB_SOURCE).then_some(source);
+        state.select_clone_source(&workspace, source);
+    }
+    for stage in cli.force {
+        state.invalidate(&workspace, stage);
+    }
+    state.save(&workspace)?;
+    run_command(&workspace, &mut state, command).await
 }
@@ -0,0 +1,116 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use serde::Deserialize;
+use tide::{Request, Response, StatusCode};
+
+use crate::utils::pretty_print;
+
+/// Canned response served by the mock GitHub responder
+#[derive(Deserialize, Debug)]
+struct FixtureResponse {
+    #[serde(default = "default_status")]
+    status: u16,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    body: serde_json::Value,
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+fn load_fixtures(fixtures_dir: &Path) -> Result<Vec<FixtureResponse>, Box<dyn Error>> {
+    let mut paths: Vec<_> = fs::read_dir(fixtures_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension() == Some(std::ffi::OsStr::new("json")))
+        .collect();
+    paths.sort();
+
+    let mut fixtures = Vec::new();
+    for path in paths {
+        let content = fs::read_to_string(&path)?;
+        let fixture = serde_json::from_str(&content)
+            .map_err(|e| format!("Invalid fixture {:?}: {}", path, e))?;
+        fixtures.push(fixture);
+    }
+    if fixtures.is_empty() {
+        return Err(format!("No fixtures found in {:?}", fixtures_dir).into());
+    }
+    Ok(fixtures)
+}
+
+/// Request bodies received by the responder, in order
+pub type ReceivedRequests = Arc<Mutex<Vec<serde_json::Value>>>;
+
+// Answers each request with the next fixture, recording its body
+fn mock_github_app(fixtures: Vec<FixtureResponse>) -> (tide::Server<()>, ReceivedRequests) {
+    let fixtures = Arc::new(fixtures);
+    let received: ReceivedRequests = Arc::default();
+    let served = Arc::new(AtomicUsize::new(0));
+    let mut app = tide::new();
+
+    let requests = Arc::clone(&received);
+    app.at("/graphql").post(move |mut req: Request<()>| {
+        let fixtures = Arc::clone(&fixtures);
+        let served = Arc::clone(&served);
+        let requests = Arc::clone(&requests);
+        async move {
+            let body: serde_json::Value = req.body_json().await.unwrap_or_default();
+            let index = served.fetch_add(1, Ordering::Relaxed);
+            pretty_print("Mock GitHub request", Some(&body["variables"]));
+            requests.lock().unwrap().push(body);
+
+            let fixture = &fixtures[index.min(fixtures.len() - 1)];
+            let status = StatusCode::try_from(fixture.status).unwrap_or(StatusCode::Ok);
+            let mut response = Response::new(status);
+            for (name, value) in fixture.headers.iter() {
+                response.insert_header(name.as_str(), value.as_str());
+            }
+            response.set_body(fixture.body.clone());
+            Ok(response)
+        }
+    });
+    (app, received)
+}
+
+/// Stands in for the GitHub GraphQL API, answering each request with the next
+/// `*.json` fixture of the directory in file name order. The last fixture is
+/// repeated once all of them were served.
+pub async fn start_mock_github(fixtures_dir: &Path, port: u16) -> Result<(), Box<dyn Error>> {
+    let (app, _) = mock_github_app(load_fixtures(fixtures_dir)?);
+    let address = format!("127.0.0.1:{}", port);
+    pretty_print(
+        "Mock GitHub started at",
+        Some(&format!("http://{}/graphql", address)),
+    );
+    app.listen(address).await?;
+    Ok(())
+}
+
+/// Starts the responder in the background on an ephemeral port, returning its GraphQL
+/// URL and the requests it receives
+#[cfg(test)]
+pub async fn spawn_mock_github(
+    fixtures_dir: &Path,
+) -> Result<(String, ReceivedRequests), Box<dyn Error>> {
+    use tide::listener::Listener;
+
+    let (app, received) = mock_github_app(load_fixtures(fixtures_dir)?);
+    let mut listener = app.bind("127.0.0.1:0").await?;
+    let info = listener
+        .info()
+        .pop()
+        .ok_or("Mock GitHub is not listening")?;
+    let url = format!("{}/graphql", info.connection());
+    async_std::task::spawn(async move { listener.accept().await });
+    Ok((url, received))
+}
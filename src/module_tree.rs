@@ -0,0 +1,334 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+use tree_sitter::Node;
+
+use crate::{
+    crate_paths::CrateTarget,
+    ignore::{collect_rust_files, IgnoreRules},
+    targets::TargetKind,
+    utils::parse_file,
+};
+
+/// How the source files of a crate are collected
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FileCollection {
+    /// Every `.rs` file under the crate directory
+    #[default]
+    Walk,
+    /// Files reachable from the target roots through `mod` declarations, `#[path]`
+    /// attributes and `include!`
+    ModuleTree,
+}
+
+/// Source files of a crate, collected by following its module tree
+pub struct ModuleTree {
+    /// Reachable files, with the kind of the first target reaching them
+    pub files: Vec<(PathBuf, TargetKind)>,
+    /// `.rs` files of the crate directory no target reaches
+    pub unreachable: Vec<PathBuf>,
+    /// Files and directories left out by the ignore rules
+    pub ignored: Vec<PathBuf>,
+}
+
+// Resolves `..` lexically, so that files reached through `#[path]` compare equal
+// to the walked ones
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            }
+            _ => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+fn node_text(node: Node, bytes: &[u8]) -> String {
+    String::from_utf8_lossy(&bytes[node.byte_range()]).to_string()
+}
+
+fn string_content(node: Node, bytes: &[u8]) -> Option<String> {
+    let content = node
+        .children(&mut node.walk())
+        .find(|n| n.kind() == "string_content")?;
+    Some(node_text(content, bytes))
+}
+
+// Paths given by the `#[path = "..."]` and `#[cfg_attr(<predicate>, path = "...")]`
+// attributes of a module. Conditional paths are all followed, along with the default one.
+fn path_attributes(node: Node, bytes: &[u8]) -> (Option<String>, Vec<String>) {
+    let mut path = None;
+    let mut conditional_paths = Vec::new();
+    let mut prev = node.prev_sibling();
+    while let Some(attribute_item) = prev.filter(|n| n.kind() == "attribute_item") {
+        prev = attribute_item.prev_sibling();
+        let Some(attribute) = attribute_item.child(2) else {
+            continue;
+        };
+        let Some(identifier) = attribute.child(0) else {
+            continue;
+        };
+        match node_text(identifier, bytes).as_str() {
+            "path" => {
+                path = attribute
+                    .child_by_field_name("value")
+                    .and_then(|value| string_content(value, bytes));
+            }
+            "cfg_attr" => {
+                let Some(arguments) = attribute.child_by_field_name("arguments") else {
+                    continue;
+                };
+                let tokens: Vec<Node> = arguments.children(&mut arguments.walk()).collect();
+                for window in tokens.windows(3) {
+                    if window[0].kind() == "identifier"
+                        && node_text(window[0], bytes) == "path"
+                        && node_text(window[1], bytes) == "="
+                        && window[2].kind() == "string_literal"
+                    {
+                        conditional_paths.extend(string_content(window[2], bytes));
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    (path, conditional_paths)
+}
+
+// Directories modules are looked up in, which differ inside inline modules
+struct ModuleDirs<'a> {
+    // Directory of the source file, where `include!` paths are relative to
+    file_dir: &'a Path,
+    // Where `#[path]` attributes are relative to
+    path_dir: PathBuf,
+    // Where `mod foo;` finds `foo.rs` or `foo/mod.rs`
+    module_dir: PathBuf,
+}
+
+// Files declared by the items of the node, as their path and whether they own
+// their directory like `mod.rs` files
+fn find_module_files(
+    node: Node,
+    bytes: &[u8],
+    dirs: &ModuleDirs,
+    files: &mut Vec<(PathBuf, bool)>,
+) {
+    for child in node.children(&mut node.walk()) {
+        match child.kind() {
+            "mod_item" => {
+                let Some(name) = child.child_by_field_name("name") else {
+                    continue;
+                };
+                let name = node_text(name, bytes);
+                let (path, conditional_paths) = path_attributes(child, bytes);
+                if let Some(body) = child.child_by_field_name("body") {
+                    let dir = dirs.module_dir.join(path.unwrap_or(name));
+                    let inline_dirs = ModuleDirs {
+                        file_dir: dirs.file_dir,
+                        path_dir: dir.clone(),
+                        module_dir: dir,
+                    };
+                    find_module_files(body, bytes, &inline_dirs, files);
+                    continue;
+                }
+                for path in conditional_paths {
+                    files.push((dirs.path_dir.join(path), true));
+                }
+                match path {
+                    Some(path) => files.push((dirs.path_dir.join(path), true)),
+                    None => {
+                        let mod_rs = dirs.module_dir.join(&name).join("mod.rs");
+                        match mod_rs.is_file() {
+                            true => files.push((mod_rs, true)),
+                            false => files.push((dirs.module_dir.join(name + ".rs"), false)),
+                        }
+                    }
+                }
+            }
+            "macro_invocation" => {
+                let is_include = child
+                    .child_by_field_name("macro")
+                    .is_some_and(|name| node_text(name, bytes) == "include");
+                let argument = child
+                    .children(&mut child.walk())
+                    .find(|n| n.kind() == "token_tree")
+                    .and_then(|tree| tree.named_child(0))
+                    .filter(|argument| argument.kind() == "string_literal");
+                if let (true, Some(argument)) = (is_include, argument) {
+                    files.extend(
+                        string_content(argument, bytes)
+                            .map(|path| (dirs.file_dir.join(path), true)),
+                    );
+                }
+            }
+            _ if child.child_count() > 0 => find_module_files(child, bytes, dirs, files),
+            _ => (),
+        }
+    }
+}
+
+/// Follows the module tree of every target from its root file
+pub fn collect_module_tree(
+    crate_dir: &Path,
+    targets: &[CrateTarget],
+    rules: &IgnoreRules,
+) -> ModuleTree {
+    let mut walked = Vec::new();
+    let mut ignored = Vec::new();
+    if let Err(e) = collect_rust_files(crate_dir, rules, &mut walked, &mut ignored) {
+        println!("Failed to read directory {:?}. Error: {}", crate_dir, e);
+    }
+
+    let mut files: Vec<(PathBuf, TargetKind)> = Vec::new();
+    for target in targets {
+        let kind = TargetKind::from_cargo_kinds(&target.kinds);
+        let mut pending = vec![(crate_dir.join(&target.src_path), true)];
+        while let Some((file, owns_dir)) = pending.pop() {
+            let file = normalize_path(&file);
+            if files.iter().any(|(reached, _)| *reached == file) || !file.is_file() {
+                continue;
+            }
+            if rules.is_ignored(&file) {
+                // Files reached through `#[path]` may be outside of the walked directory
+                if !ignored.iter().any(|path| file.starts_with(path)) {
+                    ignored.push(file);
+                }
+                continue;
+            }
+            let source = match fs::read_to_string(&file) {
+                Ok(source) => source,
+                Err(e) => {
+                    println!("Failed to read file of path {:?}. Error: {}", file, e);
+                    continue;
+                }
+            };
+            let file_dir = file.parent().unwrap_or(crate_dir);
+            let module_dir = match (owns_dir, file.file_stem()) {
+                (false, Some(stem)) => file_dir.join(stem),
+                _ => file_dir.to_path_buf(),
+            };
+            let dirs = ModuleDirs {
+                file_dir,
+                path_dir: file_dir.to_path_buf(),
+                module_dir,
+            };
+            let bytes = source.as_bytes();
+            let tree = parse_file(bytes);
+            find_module_files(tree.root_node(), bytes, &dirs, &mut pending);
+            files.push((file, kind));
+        }
+    }
+
+    let unreachable = walked
+        .into_iter()
+        .map(|path| normalize_path(&path))
+        .filter(|path| !files.iter().any(|(reached, _)| reached == path))
+        .collect();
+    ModuleTree {
+        files,
+        unreachable,
+        ignored,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{targets::discover_targets, workspace::Workspace};
+
+    #[test]
+    fn follows_module_declarations_from_the_target_roots() {
+        let root = tempfile::tempdir().unwrap();
+        let workspace = Workspace::new(root.path());
+        let crate_dir = workspace.repo_dir("repo");
+        let files = [
+            ("Cargo.toml", "[package]\nname = \"foo\"\n"),
+            (
+                "src/lib.rs",
+                r#"
+                mod a;
+                mod b;
+                mod inline {
+                    #[path = "renamed.rs"]
+                    mod c;
+                    mod d;
+                }
+                #[cfg_attr(unix, path = "sys/unix.rs")]
+                #[cfg_attr(windows, path = "sys/windows.rs")]
+                mod sys;
+                include!("generated.rs");
+                "#,
+            ),
+            ("src/main.rs", "mod cli;"),
+            ("src/cli.rs", ""),
+            // `a.rs` doesn't own `src`, `b/mod.rs` owns `src/b`
+            ("src/a.rs", "mod child;"),
+            ("src/a/child.rs", ""),
+            ("src/child.rs", ""),
+            ("src/b/mod.rs", "mod child;"),
+            ("src/b/child.rs", ""),
+            ("src/inline/renamed.rs", ""),
+            ("src/inline/d.rs", ""),
+            ("src/sys/unix.rs", ""),
+            ("src/sys/windows.rs", ""),
+            ("src/generated.rs", ""),
+            ("fixtures/input.rs", ""),
+            ("nested/Cargo.toml", "[package]\nname = \"nested\"\n"),
+            ("nested/src/lib.rs", ""),
+        ];
+        for (path, content) in files {
+            let path = crate_dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        let rules = IgnoreRules::for_repo(&workspace, &crate_dir);
+        let module_tree = collect_module_tree(&crate_dir, &discover_targets(&crate_dir), &rules);
+        let relative = |path: &Path| {
+            path.strip_prefix(&crate_dir)
+                .unwrap()
+                .to_string_lossy()
+                .to_string()
+        };
+        let mut reachable: Vec<(String, TargetKind)> = module_tree
+            .files
+            .iter()
+            .map(|(path, kind)| (relative(path), *kind))
+            .collect();
+        reachable.sort();
+        let expected: Vec<(String, TargetKind)> = [
+            ("src/a.rs", TargetKind::Lib),
+            ("src/a/child.rs", TargetKind::Lib),
+            ("src/b/child.rs", TargetKind::Lib),
+            ("src/b/mod.rs", TargetKind::Lib),
+            ("src/cli.rs", TargetKind::Bin),
+            ("src/generated.rs", TargetKind::Lib),
+            ("src/inline/d.rs", TargetKind::Lib),
+            ("src/inline/renamed.rs", TargetKind::Lib),
+            ("src/lib.rs", TargetKind::Lib),
+            ("src/main.rs", TargetKind::Bin),
+            ("src/sys/unix.rs", TargetKind::Lib),
+            ("src/sys/windows.rs", TargetKind::Lib),
+        ]
+        .iter()
+        .map(|(path, kind)| (path.to_string(), *kind))
+        .collect();
+        assert_eq!(reachable, expected);
+
+        let mut unreachable: Vec<String> = module_tree
+            .unreachable
+            .iter()
+            .map(|path| relative(path))
+            .collect();
+        unreachable.sort();
+        assert_eq!(unreachable, ["fixtures/input.rs", "src/child.rs"]);
+        assert!(module_tree.ignored.is_empty());
+    }
+}
@@ -0,0 +1,248 @@
+use std::{error::Error, fs, path::PathBuf};
+
+use crate::{
+    analyzis::analyze_crates,
+    clear_cfg::parse_code,
+    cli::PipelineCommand,
+    count_code::{count_crates_code, count_expanded_code},
+    crate_paths::{find_crate_paths, find_repos_crate_paths, CratePackages, CratePaths},
+    crates_io::{select_published_crates, unpack_published_crates},
+    data::Data,
+    expand::expand_crates,
+    git::read_revision,
+    github::{
+        clone_repos, get_most_popular_repos, repo_folder_name, restore_repos, CloneManifest,
+        Repository,
+    },
+    http::start_server,
+    results::AnalyzisResults,
+    state::{ScraperState, Stage},
+    utils::pretty_print,
+    workspace::Workspace,
+};
+
+// Every stage runs the stages it depends on first. Those return early when
+// their timestamp is already set on the state, so only missing work is done.
+
+// Directories of the fetched repositories that were cloned successfully
+async fn cloned_repos(
+    workspace: &Workspace,
+    state: &mut ScraperState,
+) -> Result<(Vec<Repository>, Vec<PathBuf>), Box<dyn Error>> {
+    let repos = get_most_popular_repos(workspace, state).await?;
+    let report = clone_repos(workspace, state, &repos).await?;
+    let repo_dirs = repos
+        .iter()
+        .map(repo_folder_name)
+        .filter(|repo_path| report.is_cloned(repo_path))
+        .map(|repo_path| workspace.repo_dir(&repo_path))
+        .collect();
+    Ok((repos, repo_dirs))
+}
+
+async fn crate_paths(
+    workspace: &Workspace,
+    state: &mut ScraperState,
+) -> Result<(Vec<Repository>, CratePaths), Box<dyn Error>> {
+    let (repos, repo_dirs) = cloned_repos(workspace, state).await?;
+    let crate_paths = find_crate_paths(workspace, state, &repo_dirs)?;
+    state.save(workspace)?;
+    Ok((repos, crate_paths))
+}
+
+// Packages are only recorded by the `cargo metadata` discovery backend
+fn add_packages(workspace: &Workspace, results: &mut AnalyzisResults) {
+    if let Some(packages) = CratePackages::load(workspace) {
+        results.add_packages(&packages);
+    }
+}
+
+async fn results(
+    workspace: &Workspace,
+    state: &mut ScraperState,
+) -> Result<AnalyzisResults, Box<dyn Error>> {
+    let (repos, crate_paths) = crate_paths(workspace, state).await?;
+    let results = AnalyzisResults::load(workspace).unwrap_or_else(|| {
+        let mut results = AnalyzisResults::from((&crate_paths, &repos));
+        add_packages(workspace, &mut results);
+        if let Some(manifest) = CloneManifest::load(workspace) {
+            results.add_revisions(&manifest);
+        }
+        results
+    });
+    results.save(workspace)?;
+    Ok(results)
+}
+
+async fn analyzed_results(
+    workspace: &Workspace,
+    state: &mut ScraperState,
+) -> Result<AnalyzisResults, Box<dyn Error>> {
+    let mut results = results(workspace, state).await?;
+    analyze_crates(workspace, state, &mut results)?;
+    count_crates_code(workspace, state, &mut results)?;
+    results.save(workspace)?;
+    Ok(results)
+}
+
+fn export(
+    workspace: &Workspace,
+    state: &mut ScraperState,
+    results: &AnalyzisResults,
+) -> Result<Data, Box<dyn Error>> {
+    let mut data: Data = results.clone().into();
+    data.date = state
+        .cloned_repos_at
+        .or(state.crates_parsed_at)
+        .expect("Repositories should have been cloned by now");
+    let serialized = serde_json::to_string(&data)?;
+    let data_json_path = workspace.data_json_path();
+    fs::write(&data_json_path, serialized)?;
+    state.complete(workspace, Stage::Export);
+    pretty_print("Data exported to", Some(&data_json_path));
+    Ok(data)
+}
+
+fn load_data(workspace: &Workspace) -> Option<Data> {
+    let string = fs::read_to_string(workspace.data_json_path()).ok()?;
+    match serde_json::from_str(&string) {
+        Ok(data) => Some(data),
+        Err(e) => {
+            println!("Error deserializing JSON: {:?}", e);
+            None
+        }
+    }
+}
+
+async fn exported_data(
+    workspace: &Workspace,
+    state: &mut ScraperState,
+) -> Result<Data, Box<dyn Error>> {
+    if state.exported_at.is_some() {
+        if let Some(data) = load_data(workspace) {
+            pretty_print("Data already exported at", Some(&state.exported_at));
+            return Ok(data);
+        }
+    }
+    let results = analyzed_results(workspace, state).await?;
+    export(workspace, state, &results)
+}
+
+async fn run_expanded(
+    workspace: &Workspace,
+    state: &mut ScraperState,
+    results: &mut AnalyzisResults,
+) -> Result<(), Box<dyn Error>> {
+    let crate_paths = CratePaths(results.crates.keys().cloned().collect());
+    parse_code(workspace, state, &crate_paths)?;
+    expand_crates(workspace, state, results).await?;
+    count_expanded_code(workspace, state, results)?;
+    Ok(())
+}
+
+pub async fn run_command(
+    workspace: &Workspace,
+    state: &mut ScraperState,
+    command: PipelineCommand,
+) -> Result<(), Box<dyn Error>> {
+    match command {
+        PipelineCommand::Run { with_expanded } => {
+            if with_expanded {
+                let mut results = analyzed_results(workspace, state).await?;
+                run_expanded(workspace, state, &mut results).await?;
+                results.save(workspace)?;
+            }
+            let data = exported_data(workspace, state).await?;
+            state.save(workspace)?;
+            start_server(data).await?;
+        }
+        PipelineCommand::Fetch => {
+            get_most_popular_repos(workspace, state).await?;
+        }
+        PipelineCommand::Clone => {
+            cloned_repos(workspace, state).await?;
+        }
+        PipelineCommand::Discover => {
+            crate_paths(workspace, state).await?;
+        }
+        PipelineCommand::Analyze => {
+            let mut results = results(workspace, state).await?;
+            analyze_crates(workspace, state, &mut results)?;
+            results.save(workspace)?;
+        }
+        PipelineCommand::Count => {
+            let mut results = results(workspace, state).await?;
+            count_crates_code(workspace, state, &mut results)?;
+        }
+        PipelineCommand::ClearCfg => {
+            let (_, crate_paths) = crate_paths(workspace, state).await?;
+            parse_code(workspace, state, &crate_paths)?;
+        }
+        PipelineCommand::Expand => {
+            let mut results = results(workspace, state).await?;
+            expand_crates(workspace, state, &mut results).await?;
+        }
+        PipelineCommand::CountExpanded => {
+            let mut results = results(workspace, state).await?;
+            count_expanded_code(workspace, state, &mut results)?;
+        }
+        PipelineCommand::Export => {
+            exported_data(workspace, state).await?;
+        }
+        PipelineCommand::Serve => {
+            let data = exported_data(workspace, state).await?;
+            state.save(workspace)?;
+            start_server(data).await?;
+        }
+        PipelineCommand::Local { serve, .. } => {
+            let crate_paths = find_repos_crate_paths(workspace, state, workspace.local_repos())?;
+            let mut results = AnalyzisResults::from(&crate_paths);
+            add_packages(workspace, &mut results);
+            for (repo_path, repo_analyzis) in results.repos.iter_mut() {
+                // Local directories aren't necessarily git repositories
+                repo_analyzis.revision = read_revision(&workspace.repo_dir(repo_path)).await.ok();
+            }
+            analyze_crates(workspace, state, &mut results)?;
+            count_crates_code(workspace, state, &mut results)?;
+            results.save(workspace)?;
+            let data = export(workspace, state, &results)?;
+            state.save(workspace)?;
+            if serve {
+                start_server(data).await?;
+            }
+        }
+        PipelineCommand::Crates {
+            index,
+            crates_dir,
+            downloads,
+            count,
+            serve,
+        } => {
+            let crates = select_published_crates(&index, &crates_dir, downloads.as_deref(), count)?;
+            let crates = unpack_published_crates(workspace, state, crates).await?;
+            let repo_dirs: Vec<PathBuf> = crates
+                .0
+                .iter()
+                .map(|published_crate| workspace.repo_dir(&published_crate.repo_path()))
+                .collect();
+            let crate_paths = find_repos_crate_paths(workspace, state, &repo_dirs)?;
+            let mut results = AnalyzisResults::from(&crate_paths);
+            add_packages(workspace, &mut results);
+            results.add_downloads(&crates);
+            analyze_crates(workspace, state, &mut results)?;
+            count_crates_code(workspace, state, &mut results)?;
+            results.save(workspace)?;
+            let data = export(workspace, state, &results)?;
+            state.save(workspace)?;
+            if serve {
+                start_server(data).await?;
+            }
+        }
+        PipelineCommand::Restore { manifest } => {
+            let manifest = CloneManifest::read(&manifest)?;
+            restore_repos(workspace, state, manifest).await?;
+        }
+    }
+    state.save(workspace)?;
+    Ok(())
+}
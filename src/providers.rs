@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+};
+use ts_rs::TS;
+
+use crate::{
+    analyzis::{MacroAnalyzis, MacroUsage},
+    cargo::CargoToml,
+    macro_paths::LOCAL_PREFIXES,
+};
+
+/// Provider of the macros of the standard library, `std`, `core` and `alloc`
+pub const STD_PROVIDER: &str = "std";
+/// Provider of the macros defined by the analyzed crate itself
+pub const LOCAL_PROVIDER: &str = "local";
+/// Provider of the macros whose path couldn't be resolved to a dependency
+pub const UNKNOWN_PROVIDER: &str = "unknown";
+
+const STD_CRATES: [&str; 3] = ["std", "core", "alloc"];
+
+/// Number of derives, attribute macro and function-like macro invocations of the
+/// macros of a crate
+#[derive(TS, Serialize, Deserialize, Default, Debug, Clone, Copy)]
+pub struct ProviderMacroUsage {
+    pub derives: usize,
+    pub attribute_macro_invocations: usize,
+    pub macro_invocations: usize,
+}
+
+impl std::ops::AddAssign for ProviderMacroUsage {
+    fn add_assign(&mut self, rhs: Self) {
+        self.derives += rhs.derives;
+        self.attribute_macro_invocations += rhs.attribute_macro_invocations;
+        self.macro_invocations += rhs.macro_invocations;
+    }
+}
+
+/// Macro usage by providing crate, e.g. `std`, `serde`, `local` or `unknown`
+pub type ProvidersMacroUsage = BTreeMap<String, ProviderMacroUsage>;
+
+pub fn merge_providers_usage(usage: &mut ProvidersMacroUsage, rhs: &ProvidersMacroUsage) {
+    for (provider, provider_usage) in rhs {
+        *usage.entry(provider.clone()).or_default() += *provider_usage;
+    }
+}
+
+/// Crates whose macros a crate can use: its own and its dependencies, by the name
+/// they are referred to in paths
+#[derive(Default, Debug)]
+pub struct CrateDependencies {
+    own_names: Vec<String>,
+    // Name in paths, with the name of the package
+    dependencies: HashMap<String, String>,
+}
+
+impl CrateDependencies {
+    /// Reads every dependency table of the manifest, including dev, build and
+    /// platform specific ones
+    pub fn read(crate_dir: &Path) -> Self {
+        let manifest = fs::read_to_string(crate_dir.join("Cargo.toml")).unwrap_or_default();
+        let manifest: CargoToml = toml::from_str(&manifest).unwrap_or_default();
+
+        let mut own_names = Vec::new();
+        if let Some(package) = &manifest.package {
+            own_names.push(package.name.replace('-', "_"));
+        }
+        if let Some(name) = manifest.lib.as_ref().and_then(|lib| lib.name.as_ref()) {
+            own_names.push(name.replace('-', "_"));
+        }
+
+        let mut dependencies = HashMap::new();
+        let tables = std::iter::once(&manifest.dependencies)
+            .chain(manifest.target.iter().flat_map(|targets| targets.values()));
+        for table in tables {
+            for (name, dependency) in table
+                .dependencies
+                .iter()
+                .chain(&table.dev_dependencies)
+                .chain(&table.build_dependencies)
+            {
+                // `foo = { package = "bar" }` is referred to as `foo`
+                let package = dependency
+                    .get("package")
+                    .and_then(|package| package.as_str())
+                    .unwrap_or(name);
+                dependencies.insert(name.replace('-', "_"), package.to_string());
+            }
+        }
+        Self {
+            own_names,
+            dependencies,
+        }
+    }
+
+    /// Crate providing the macro of the canonical path
+    pub fn provider(&self, path: &str) -> String {
+        let Some((first, _)) = path.split_once("::") else {
+            return UNKNOWN_PROVIDER.to_string();
+        };
+        if STD_CRATES.contains(&first) {
+            return STD_PROVIDER.to_string();
+        }
+        if LOCAL_PREFIXES.contains(&first) || self.own_names.iter().any(|name| name == first) {
+            return LOCAL_PROVIDER.to_string();
+        }
+        match self.dependencies.get(first) {
+            Some(package) => package.clone(),
+            None => UNKNOWN_PROVIDER.to_string(),
+        }
+    }
+
+    fn add_usage(
+        &self,
+        usage: &mut ProvidersMacroUsage,
+        resolved: &MacroUsage,
+        field: fn(&mut ProviderMacroUsage) -> &mut usize,
+    ) {
+        for (path, count) in &resolved.0 {
+            *field(usage.entry(self.provider(path)).or_default()) += count;
+        }
+    }
+
+    /// Attributes the resolved derives, attribute macros and function-like macros
+    /// to their providing crates. Built-in attributes aren't macros of any crate.
+    pub fn providers_usage(&self, analyzis: &MacroAnalyzis) -> ProvidersMacroUsage {
+        let mut usage = ProvidersMacroUsage::new();
+        self.add_usage(
+            &mut usage,
+            &analyzis.derive_macro_usage.resolved_derives,
+            |usage| &mut usage.derives,
+        );
+        self.add_usage(
+            &mut usage,
+            &analyzis.resolved_attribute_macro_invocations,
+            |usage| &mut usage.attribute_macro_invocations,
+        );
+        self.add_usage(&mut usage, &analyzis.resolved_macro_invocations, |usage| {
+            &mut usage.macro_invocations
+        });
+        usage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_providers_of_every_dependency_table() {
+        let crate_dir = tempfile::tempdir().unwrap();
+        fs::write(
+            crate_dir.path().join("Cargo.toml"),
+            r#"
+            [package]
+            name = "my-crate"
+
+            [lib]
+            name = "my_lib"
+
+            [dependencies]
+            serde = "1"
+            json = { package = "serde_json", version = "1" }
+            tokio-util = "0.7"
+
+            [dev-dependencies]
+            proptest = "1"
+
+            [build-dependencies]
+            cc = "1"
+
+            [target.'cfg(unix)'.dependencies]
+            nix = "0.29"
+
+            [target.'cfg(windows)'.dev-dependencies]
+            win = { package = "windows-sys", version = "0.59" }
+            "#,
+        )
+        .unwrap();
+        let dependencies = CrateDependencies::read(crate_dir.path());
+
+        let providers = [
+            ("std::println", STD_PROVIDER),
+            ("core::fmt::Debug", STD_PROVIDER),
+            ("alloc::vec", STD_PROVIDER),
+            ("crate::m", LOCAL_PROVIDER),
+            ("self::m", LOCAL_PROVIDER),
+            ("super::m", LOCAL_PROVIDER),
+            ("$crate::m", LOCAL_PROVIDER),
+            ("my_crate::m", LOCAL_PROVIDER),
+            ("my_lib::m", LOCAL_PROVIDER),
+            ("serde::Serialize", "serde"),
+            ("json::json", "serde_json"),
+            ("tokio_util::m", "tokio-util"),
+            ("proptest::proptest", "proptest"),
+            ("cc::m", "cc"),
+            ("nix::m", "nix"),
+            ("win::m", "windows-sys"),
+            ("anyhow::bail", UNKNOWN_PROVIDER),
+            ("println", UNKNOWN_PROVIDER),
+        ];
+        for (path, provider) in providers {
+            assert_eq!(dependencies.provider(path), provider, "{}", path);
+        }
+    }
+}
@@ -1,15 +1,18 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use ts_rs::TS;
 
 use crate::{
-    analyzis::MacroAnalyzis,
-    crate_paths::{get_repo_path, CratePaths},
-    github::Repository,
+    analyzis::{ClassMacroUsage, MacroAnalyzis, TargetMacroUsage},
+    crate_paths::{get_repo_path, CratePackage, CratePackages, CratePaths},
+    crates_io::PublishedCrates,
+    git::RepoRevision,
+    github::{repo_folder_name, CloneManifest, Repository},
+    providers::ProvidersMacroUsage,
+    source_class::SourceClass,
+    workspace::Workspace,
 };
 
-const RESULTS_PATH: &str = "./data/analyzis.ron";
-
 type RepoPath = String;
 type CratePath = String;
 
@@ -31,11 +34,34 @@ impl std::ops::Add for CharLineCount {
     }
 }
 
+/// Characters and lines of authored, vendored and generated files
+pub type ClassSourceCount = BTreeMap<SourceClass, CharLineCount>;
+
+pub fn merge_source_count(count: &mut ClassSourceCount, rhs: &ClassSourceCount) {
+    for (class, c) in rhs {
+        *count.entry(*class).or_default() += *c;
+    }
+}
+
 #[derive(TS, Serialize, Deserialize, Default, Debug, Clone)]
 pub struct CrateAnalyzis {
+    pub package: Option<CratePackage>,
     pub source_count: Option<CharLineCount>,
+    /// `source_count` broken down by authored, vendored and generated files
+    pub class_source_count: Option<ClassSourceCount>,
     pub expanded_count: Option<Result<CharLineCount, String>>,
     pub macro_usage: Option<MacroAnalyzis>,
+    /// `macro_usage` broken down by the Cargo target kind of each file
+    pub target_macro_usage: Option<TargetMacroUsage>,
+    /// `macro_usage` broken down by authored, vendored and generated files
+    pub class_macro_usage: Option<ClassMacroUsage>,
+    /// Resolved macros of `macro_usage` attributed to the crates providing them
+    pub provider_macro_usage: Option<ProvidersMacroUsage>,
+    /// Files of the crate directory outside of the module tree, relative to it. Only
+    /// collected when following the module tree
+    pub unreachable_files: Option<Vec<String>>,
+    /// Files and directories left out by the ignore rules, relative to the crate directory
+    pub ignored_paths: Option<Vec<String>>,
 }
 
 #[derive(TS, Serialize, Deserialize, Default, Debug, Clone)]
@@ -43,15 +69,27 @@ pub struct RepoAnalyzis {
     pub path: RepoPath,
     pub crates_count: usize,
     pub source_count: Option<CharLineCount>,
+    pub class_source_count: Option<ClassSourceCount>,
     pub expanded_count: Option<Result<CharLineCount, usize>>,
     pub macro_usage: Option<MacroAnalyzis>,
+    pub target_macro_usage: Option<TargetMacroUsage>,
+    pub class_macro_usage: Option<ClassMacroUsage>,
+    pub provider_macro_usage: Option<ProvidersMacroUsage>,
     pub star_count: i64,
+    /// Commits the repository was analyzed at
+    pub revision: Option<RepoRevision>,
+    /// crates.io downloads, measuring the popularity of published crates instead of stars
+    pub downloads: Option<u64>,
 }
 
 #[derive(TS, Serialize, Deserialize, Default, Debug, Clone)]
 pub struct OverallAnalyzis {
     pub source_count: Option<CharLineCount>,
+    pub class_source_count: Option<ClassSourceCount>,
     pub macro_usage: Option<MacroAnalyzis>,
+    pub target_macro_usage: Option<TargetMacroUsage>,
+    pub class_macro_usage: Option<ClassMacroUsage>,
+    pub provider_macro_usage: Option<ProvidersMacroUsage>,
 }
 
 #[derive(TS, Serialize, Deserialize, Default, Debug, Clone)]
@@ -61,8 +99,8 @@ pub struct AnalyzisResults {
     pub overall: OverallAnalyzis,
 }
 
-impl From<(&CratePaths, &Vec<Repository>)> for AnalyzisResults {
-    fn from((paths, repos_query): (&CratePaths, &Vec<Repository>)) -> Self {
+impl From<&CratePaths> for AnalyzisResults {
+    fn from(paths: &CratePaths) -> Self {
         let mut crates = HashMap::new();
         let mut repos: HashMap<String, RepoAnalyzis> = HashMap::new();
         for path in paths {
@@ -73,25 +111,9 @@ impl From<(&CratePaths, &Vec<Repository>)> for AnalyzisResults {
                 },
             );
             let repo_path = get_repo_path(path);
-            let mut repo_analyzis = match repos.get(&repo_path) {
-                Some(repo_analyzis) => repo_analyzis.clone(),
-                None => RepoAnalyzis {
-                    star_count: repos_query
-                        .iter()
-                        .find(|repository| {
-                            let folder_name =
-                                format!("{}.{}", repository.owner.login, repository.name);
-                            folder_name == repo_path
-                        })
-                        .unwrap()
-                        .stargazers
-                        .total_count,
-                    ..Default::default()
-                },
-            };
-            repo_analyzis.path = repo_path.to_string();
+            let repo_analyzis = repos.entry(repo_path.clone()).or_default();
+            repo_analyzis.path = repo_path;
             repo_analyzis.crates_count += 1;
-            repos.insert(repo_path, repo_analyzis);
         }
         Self {
             crates,
@@ -101,7 +123,47 @@ impl From<(&CratePaths, &Vec<Repository>)> for AnalyzisResults {
     }
 }
 
+impl From<(&CratePaths, &Vec<Repository>)> for AnalyzisResults {
+    fn from((paths, repos_query): (&CratePaths, &Vec<Repository>)) -> Self {
+        let mut results = Self::from(paths);
+        for (repo_path, repo_analyzis) in results.repos.iter_mut() {
+            // Repositories no longer fetched, e.g. left over by a previous selection, have no stars
+            if let Some(repository) = repos_query
+                .iter()
+                .find(|repository| &repo_folder_name(repository) == repo_path)
+            {
+                repo_analyzis.star_count = repository.stargazers.total_count;
+            }
+        }
+        results
+    }
+}
+
 impl AnalyzisResults {
+    pub fn add_packages(&mut self, packages: &CratePackages) {
+        for (crate_path, package) in &packages.0 {
+            if let Some(crate_analyzis) = self.crates.get_mut(crate_path) {
+                crate_analyzis.package = Some(package.clone());
+            }
+        }
+    }
+
+    pub fn add_downloads(&mut self, crates: &PublishedCrates) {
+        for published_crate in &crates.0 {
+            if let Some(repo_analyzis) = self.repos.get_mut(&published_crate.repo_path()) {
+                repo_analyzis.downloads = Some(published_crate.downloads);
+            }
+        }
+    }
+
+    pub fn add_revisions(&mut self, manifest: &CloneManifest) {
+        for pinned in &manifest.0 {
+            if let Some(repo_analyzis) = self.repos.get_mut(&pinned.repo_path()) {
+                repo_analyzis.revision = Some(pinned.revision.clone());
+            }
+        }
+    }
+
     pub fn update_repo(&mut self, repo_path: &str, update: &mut dyn FnMut(&mut RepoAnalyzis)) {
         let mut repo_analyzis = self
             .repos
@@ -130,6 +192,63 @@ impl AnalyzisResults {
 
         self.crates.insert(crate_path.to_string(), crate_analyzis);
     }
+
+    pub fn clear_macro_usage(&mut self) {
+        for c in self.crates.values_mut() {
+            c.macro_usage = None;
+            c.target_macro_usage = None;
+            c.class_macro_usage = None;
+            c.provider_macro_usage = None;
+            c.unreachable_files = None;
+            c.ignored_paths = None;
+        }
+        for r in self.repos.values_mut() {
+            r.macro_usage = None;
+            r.target_macro_usage = None;
+            r.class_macro_usage = None;
+            r.provider_macro_usage = None;
+        }
+        self.overall.macro_usage = None;
+        self.overall.target_macro_usage = None;
+        self.overall.class_macro_usage = None;
+        self.overall.provider_macro_usage = None;
+    }
+
+    pub fn clear_source_count(&mut self) {
+        for c in self.crates.values_mut() {
+            c.source_count = None;
+            c.class_source_count = None;
+        }
+        for r in self.repos.values_mut() {
+            r.source_count = None;
+            r.class_source_count = None;
+        }
+        self.overall.source_count = None;
+        self.overall.class_source_count = None;
+    }
+
+    pub fn clear_expanded_count(&mut self) {
+        self.crates
+            .values_mut()
+            .for_each(|c| c.expanded_count = None);
+        self.repos
+            .values_mut()
+            .for_each(|r| r.expanded_count = None);
+    }
+
+    /// Clears the counted expanded code, keeping the expansion failures
+    pub fn clear_expanded_ok_count(&mut self) {
+        for c in self.crates.values_mut() {
+            if let Some(Ok(_)) = c.expanded_count {
+                c.expanded_count = None;
+            }
+        }
+        for r in self.repos.values_mut() {
+            if let Some(Ok(_)) = r.expanded_count {
+                r.expanded_count = None;
+            }
+        }
+    }
 }
 
-impl_save_load!(AnalyzisResults, RESULTS_PATH);
+impl_save_load!(AnalyzisResults, Workspace::results_path);
@@ -0,0 +1,179 @@
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use crate::github::{repo_folder_name, Repository};
+
+const ARCHIVE_EXTENSIONS: [&str; 3] = [".tar.gz", ".tgz", ".crate"];
+/// File of an unpacked folder recording the archive it was unpacked from
+const ARCHIVE_STAMP: &str = ".archive-stamp";
+
+/// Where a repository is cloned from
+#[derive(Debug, Clone, PartialEq)]
+pub enum RepoSource {
+    /// Git remote, either a URL or the path of a repository
+    Git(String),
+    /// Gzipped tarball with the sources in a single top-level folder, as GitHub
+    /// archives and crates.io `.crate` files
+    Archive(PathBuf),
+}
+
+impl RepoSource {
+    /// Finds the repository in the source, which defaults to its GitHub URL. A
+    /// `file://` or other remote base is expected to host `<owner>.<name>.git`,
+    /// while a local directory may also hold a `<owner>.<name>` checkout or archive
+    pub fn resolve(source: Option<&str>, repository: &Repository) -> Self {
+        let Some(base) = source else {
+            return RepoSource::Git(repository.url.clone());
+        };
+        let folder_name = repo_folder_name(repository);
+        if base.contains("://") {
+            return RepoSource::Git(format!(
+                "{}/{}.git",
+                base.trim_end_matches('/'),
+                folder_name
+            ));
+        }
+
+        // Relative to the working directory, while git resolves a relative remote
+        // from the repository folder
+        let dir = Path::new(base)
+            .canonicalize()
+            .unwrap_or_else(|_| PathBuf::from(base));
+        for extension in ARCHIVE_EXTENSIONS {
+            let archive = dir.join(format!("{}{}", folder_name, extension));
+            if archive.is_file() {
+                return RepoSource::Archive(archive);
+            }
+        }
+        let mirror = dir.join(format!("{}.git", folder_name));
+        let remote = match mirror.exists() {
+            true => mirror,
+            false => dir.join(folder_name),
+        };
+        RepoSource::Git(remote.to_string_lossy().to_string())
+    }
+}
+
+// Identifies the archive a folder was unpacked from
+fn archive_stamp(archive: &Path) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let metadata = std::fs::metadata(archive)?;
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?;
+    Ok(format!(
+        "{}\n{}\n{}\n",
+        archive.display(),
+        metadata.len(),
+        modified.as_nanos()
+    ))
+}
+
+/// Unpacks the archive into the folder, replacing its previous content. Returns
+/// `false` without unpacking when the folder already holds the same archive
+pub async fn unpack_archive(
+    archive: &Path,
+    path: &Path,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let stamp = archive_stamp(archive)?;
+    let stamp_path = path.join(ARCHIVE_STAMP);
+    if std::fs::read_to_string(&stamp_path).is_ok_and(|previous| previous == stamp) {
+        return Ok(false);
+    }
+    if path.exists() {
+        std::fs::remove_dir_all(path)?;
+    }
+    std::fs::create_dir_all(path)?;
+    let output = tokio::process::Command::new("tar")
+        .arg("-xzf")
+        .arg(archive)
+        .arg("--strip-components=1")
+        .arg("-C")
+        .arg(path)
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to unpack {:?}: {}",
+            archive,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    std::fs::write(stamp_path, stamp)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn repository(name_with_owner: &str) -> Repository {
+        let (owner, name) = name_with_owner.split_once('/').unwrap();
+        serde_json::from_value(serde_json::json!({
+            "nameWithOwner": name_with_owner,
+            "name": name,
+            "id": "R_0",
+            "owner": { "__typename": "User", "login": owner },
+            "url": format!("https://github.com/{}", name_with_owner),
+            "stargazers": { "totalCount": 1 },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn resolves_remotes() {
+        let repository = repository("owner/repo");
+        assert_eq!(
+            RepoSource::resolve(None, &repository),
+            RepoSource::Git("https://github.com/owner/repo".to_string())
+        );
+        assert_eq!(
+            RepoSource::resolve(Some("file:///srv/mirrors/"), &repository),
+            RepoSource::Git("file:///srv/mirrors/owner.repo.git".to_string())
+        );
+    }
+
+    #[test]
+    fn prefers_archives_then_mirrors_then_checkouts() {
+        let source = tempfile::tempdir().unwrap();
+        let dir = source.path().canonicalize().unwrap();
+        let base = dir.to_str().unwrap();
+        let repository = repository("owner/repo");
+        fs::create_dir(dir.join("owner.repo")).unwrap();
+        let checkout = dir.join("owner.repo").to_string_lossy().to_string();
+        assert_eq!(
+            RepoSource::resolve(Some(base), &repository),
+            RepoSource::Git(checkout)
+        );
+
+        fs::create_dir(dir.join("owner.repo.git")).unwrap();
+        let mirror = dir.join("owner.repo.git").to_string_lossy().to_string();
+        assert_eq!(
+            RepoSource::resolve(Some(base), &repository),
+            RepoSource::Git(mirror)
+        );
+
+        fs::write(dir.join("owner.repo.crate"), "").unwrap();
+        assert_eq!(
+            RepoSource::resolve(Some(base), &repository),
+            RepoSource::Archive(dir.join("owner.repo.crate"))
+        );
+    }
+
+    #[test]
+    fn resolves_relative_directories_from_the_working_directory() {
+        let source = tempfile::tempdir_in(".").unwrap();
+        let name = source.path().file_name().unwrap().to_str().unwrap();
+        let mirror = source.path().canonicalize().unwrap().join("owner.repo.git");
+        fs::create_dir(&mirror).unwrap();
+
+        let resolved = RepoSource::resolve(Some(&format!("./{}", name)), &repository("owner/repo"));
+        assert_eq!(
+            resolved,
+            RepoSource::Git(mirror.to_string_lossy().to_string())
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use ts_rs::TS;
+
+// Directories holding copies of other projects' code
+const VENDORED_DIRS: [&str; 5] = [
+    "vendor",
+    "vendored",
+    "third_party",
+    "third-party",
+    "thirdparty",
+];
+// Directories and file name suffixes of checked in generated code, e.g. copied from `OUT_DIR`
+const GENERATED_DIRS: [&str; 3] = ["generated", "autogenerated", "out"];
+const GENERATED_SUFFIXES: [&str; 4] = [".generated.rs", "_generated.rs", ".pb.rs", "_pb.rs"];
+// Markers of generated code in the leading comments, lowercased
+const GENERATED_MARKERS: [&str; 6] = [
+    "@generated",
+    "automatically generated",
+    "auto-generated",
+    "autogenerated",
+    "do not edit",
+    "generated by",
+];
+// Number of leading lines searched for generated code markers
+const HEADER_LINES: usize = 10;
+
+/// Whether a source file was written for the project, copied from another one or generated
+#[derive(TS, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceClass {
+    Authored,
+    Vendored,
+    Generated,
+}
+
+// Outer and inner doc comments, `///`, `//!`, `/** */` and `/*! */`, whose text
+// documents the code rather than how it was produced
+fn starts_doc_comment(line: &str) -> bool {
+    (line.starts_with("///") && !line.starts_with("////"))
+        || line.starts_with("//!")
+        || (line.starts_with("/**") && !line.starts_with("/***") && !line.starts_with("/**/"))
+        || line.starts_with("/*!")
+}
+
+fn has_generated_header(source: &str) -> bool {
+    let mut in_doc_block = false;
+    for line in source.lines().take(HEADER_LINES).map(str::trim_start) {
+        if in_doc_block || starts_doc_comment(line) {
+            // Doc block comments run until their closing `*/`
+            in_doc_block = (in_doc_block || line.starts_with("/*")) && !line.contains("*/");
+            continue;
+        }
+        let is_comment = line.starts_with("//") || line.starts_with("/*") || line.starts_with('*');
+        let line = line.to_lowercase();
+        if is_comment && GENERATED_MARKERS.iter().any(|marker| line.contains(marker)) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Classifies a file from its path relative to the repository and its leading comments.
+/// Vendored trees win over generated files, since they are not the project's code either way.
+pub fn classify_file(relative_path: &Path, source: &str) -> SourceClass {
+    let dirs: Vec<String> = relative_path
+        .parent()
+        .map(|parent| {
+            parent
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy().to_lowercase())
+                .collect()
+        })
+        .unwrap_or_default();
+    if dirs.iter().any(|dir| VENDORED_DIRS.contains(&dir.as_str())) {
+        return SourceClass::Vendored;
+    }
+    let file_name = relative_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if dirs
+        .iter()
+        .any(|dir| GENERATED_DIRS.contains(&dir.as_str()))
+        || GENERATED_SUFFIXES
+            .iter()
+            .any(|suffix| file_name.ends_with(suffix))
+        || has_generated_header(source)
+    {
+        return SourceClass::Generated;
+    }
+    SourceClass::Authored
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify(path: &str, source: &str) -> SourceClass {
+        classify_file(Path::new(path), source)
+    }
+
+    #[test]
+    fn classifies_files_by_their_directories_and_names() {
+        assert_eq!(classify("src/lib.rs", ""), SourceClass::Authored);
+        assert_eq!(classify("Vendor/zlib/lib.rs", ""), SourceClass::Vendored);
+        assert_eq!(
+            classify("src/third_party/out/a.rs", ""),
+            SourceClass::Vendored
+        );
+        assert_eq!(classify("src/generated/a.rs", ""), SourceClass::Generated);
+        assert_eq!(classify("src/proto/api.pb.rs", ""), SourceClass::Generated);
+        assert_eq!(
+            classify("src/schema_generated.rs", ""),
+            SourceClass::Generated
+        );
+        // Only directories are matched by name
+        assert_eq!(classify("src/out.rs", ""), SourceClass::Authored);
+    }
+
+    #[test]
+    fn classifies_files_by_the_comments_of_their_header() {
+        let generated = [
+            "// @generated\nfn main() {}",
+            "#![allow(clippy::all)]\n// This file is automatically generated by build.rs",
+            "/*\n * DO NOT EDIT\n */",
+        ];
+        for source in generated {
+            assert_eq!(classify("src/a.rs", source), SourceClass::Generated);
+        }
+
+        let authored = [
+            "//! Types generated by the `bindings` macro\nmod types;",
+            "/// Do not edit the returned buffer\nfn buffer() {}",
+            "/*!\n * Parsers generated by the grammar\n */",
+            "/** Auto-generated identifiers */\nstruct Id;",
+            "fn generated_by() -> &'static str { \"@generated\" }",
+            // Past the header
+            &format!("{}// @generated", "\n".repeat(HEADER_LINES)),
+        ];
+        for source in authored {
+            assert_eq!(classify("src/a.rs", source), SourceClass::Authored);
+        }
+    }
+}
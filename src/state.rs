@@ -1,9 +1,67 @@
 use chrono::prelude::*;
 use chrono::DateTime;
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
-use std::fmt::Debug;
+use std::{fmt::Debug, path::PathBuf};
 
-const STATE_PATH: &str = "./data/state.ron";
+use crate::{
+    crate_paths::DiscoveryBackend, git::CloneStrategy, github::RepoSelection,
+    module_tree::FileCollection, utils::pretty_print, workspace::Workspace,
+};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    Fetch,
+    Clone,
+    Discover,
+    Analyze,
+    Count,
+    ClearCfg,
+    Expand,
+    CountExpanded,
+    Export,
+}
+
+impl Stage {
+    const ALL: [Stage; 9] = [
+        Stage::Fetch,
+        Stage::Clone,
+        Stage::Discover,
+        Stage::Analyze,
+        Stage::Count,
+        Stage::ClearCfg,
+        Stage::Expand,
+        Stage::CountExpanded,
+        Stage::Export,
+    ];
+
+    /// Stages whose output is read by this stage
+    fn dependencies(self) -> &'static [Stage] {
+        match self {
+            Stage::Fetch => &[],
+            Stage::Clone => &[Stage::Fetch],
+            Stage::Discover => &[Stage::Clone],
+            Stage::Analyze | Stage::Count | Stage::ClearCfg | Stage::Expand => &[Stage::Discover],
+            Stage::CountExpanded => &[Stage::ClearCfg, Stage::Expand],
+            // `--with-expanded` exports the expanded counts too
+            Stage::Export => &[Stage::Analyze, Stage::Count, Stage::CountExpanded],
+        }
+    }
+
+    /// Files persisted by this stage that are removed when it is invalidated
+    fn artifacts(self, workspace: &Workspace) -> Vec<PathBuf> {
+        match self {
+            Stage::Discover => vec![
+                workspace.crate_paths_path(),
+                workspace.crate_packages_path(),
+                workspace.results_path(),
+            ],
+            Stage::ClearCfg => vec![workspace.ranges_to_remove_path()],
+            Stage::Export => vec![workspace.data_json_path()],
+            _ => vec![],
+        }
+    }
+}
 
 #[derive(Serialize, Deserialize, Default, Debug)]
 pub struct ScraperState {
@@ -15,6 +73,112 @@ pub struct ScraperState {
     pub analyzed_macros_at: Option<DateTime<Local>>,
     pub counted_expanded_chars_at: Option<DateTime<Local>>,
     pub cleared_cfg_at: Option<DateTime<Local>>,
+    pub exported_at: Option<DateTime<Local>>,
+    #[serde(default)]
+    pub repos_selection: RepoSelection,
+    #[serde(default)]
+    pub clone_strategy: CloneStrategy,
+    /// Mirror directory, remote or archive directory cloned from instead of GitHub
+    #[serde(default)]
+    pub clone_source: Option<String>,
+    #[serde(default)]
+    pub discovery: DiscoveryBackend,
+    #[serde(default)]
+    pub file_collection: FileCollection,
 }
 
-impl_save_load!(ScraperState, STATE_PATH);
+impl_save_load!(ScraperState, Workspace::state_path);
+
+impl ScraperState {
+    fn stage_timestamp(&mut self, stage: Stage) -> &mut Option<DateTime<Local>> {
+        match stage {
+            Stage::Fetch => &mut self.repos_query_at,
+            Stage::Clone => &mut self.cloned_repos_at,
+            Stage::Discover => &mut self.crates_parsed_at,
+            Stage::Analyze => &mut self.analyzed_macros_at,
+            Stage::Count => &mut self.counted_code_at,
+            Stage::ClearCfg => &mut self.cleared_cfg_at,
+            Stage::Expand => &mut self.expanded_macros_at,
+            Stage::CountExpanded => &mut self.counted_expanded_chars_at,
+            Stage::Export => &mut self.exported_at,
+        }
+    }
+
+    /// Marks the stage as completed now. Every stage depending on it is invalidated,
+    /// since its output was computed from the previous run.
+    pub fn complete(&mut self, workspace: &Workspace, stage: Stage) {
+        self.invalidate_dependents(workspace, stage);
+        *self.stage_timestamp(stage) = Some(Local::now());
+    }
+
+    /// Clears the timestamp and artifacts of the stage and of every stage depending on it,
+    /// so they run again next time.
+    pub fn invalidate(&mut self, workspace: &Workspace, stage: Stage) {
+        if self.stage_timestamp(stage).take().is_some() {
+            pretty_print("Invalidated stage", Some(&stage));
+        }
+        for artifact in stage.artifacts(workspace) {
+            match std::fs::remove_file(&artifact) {
+                Ok(()) => pretty_print("Removed", Some(&artifact)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+                Err(e) => println!("Failed to remove {:?}: {}", artifact, e),
+            }
+        }
+        self.invalidate_dependents(workspace, stage);
+    }
+
+    /// Records which repositories should be fetched, invalidating the fetched ones if it changed
+    pub fn select_repos(&mut self, workspace: &Workspace, selection: RepoSelection) {
+        if self.repos_selection != selection {
+            pretty_print("Repository selection changed", Some(&selection));
+            self.repos_selection = selection;
+            self.invalidate(workspace, Stage::Fetch);
+        }
+    }
+
+    /// Records how repositories are cloned, invalidating the cloned ones if it changed
+    pub fn select_clone_strategy(&mut self, workspace: &Workspace, strategy: CloneStrategy) {
+        if self.clone_strategy != strategy {
+            pretty_print("Clone strategy changed", Some(&strategy));
+            self.clone_strategy = strategy;
+            self.invalidate(workspace, Stage::Clone);
+        }
+    }
+
+    /// Records where repositories are cloned from, invalidating the cloned ones if it changed
+    pub fn select_clone_source(&mut self, workspace: &Workspace, source: Option<String>) {
+        if self.clone_source != source {
+            pretty_print("Clone source changed", Some(&source));
+            self.clone_source = source;
+            self.invalidate(workspace, Stage::Clone);
+        }
+    }
+
+    /// Records how crates are found, invalidating the found ones if it changed
+    pub fn select_discovery(&mut self, workspace: &Workspace, discovery: DiscoveryBackend) {
+        if self.discovery != discovery {
+            pretty_print("Discovery backend changed", Some(&discovery));
+            self.discovery = discovery;
+            self.invalidate(workspace, Stage::Discover);
+        }
+    }
+
+    /// Records how the files of each crate are collected, invalidating the analyzed
+    /// and counted crates if it changed
+    pub fn select_file_collection(&mut self, workspace: &Workspace, collection: FileCollection) {
+        if self.file_collection != collection {
+            pretty_print("File collection changed", Some(&collection));
+            self.file_collection = collection;
+            self.invalidate(workspace, Stage::Analyze);
+            self.invalidate(workspace, Stage::Count);
+        }
+    }
+
+    fn invalidate_dependents(&mut self, workspace: &Workspace, stage: Stage) {
+        for dependent in Stage::ALL {
+            if dependent.dependencies().contains(&stage) {
+                self.invalidate(workspace, dependent);
+            }
+        }
+    }
+}
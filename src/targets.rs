@@ -0,0 +1,354 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use ts_rs::TS;
+
+use crate::{
+    cargo::{CargoToml, CargoTomlTarget},
+    crate_paths::{CratePackage, CrateTarget},
+};
+
+/// Kind of Cargo target a source file is compiled into
+#[derive(TS, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetKind {
+    Lib,
+    ProcMacro,
+    Bin,
+    Example,
+    Test,
+    Bench,
+    /// Build script
+    Build,
+    /// Files no target reaches, such as fixtures
+    Other,
+}
+
+impl TargetKind {
+    /// Kind of a target from its `cargo metadata` kinds
+    pub fn from_cargo_kinds(kinds: &[String]) -> Self {
+        let Some(kind) = kinds.first() else {
+            return TargetKind::Other;
+        };
+        match kind.as_str() {
+            "lib" | "rlib" | "dylib" | "cdylib" | "staticlib" => TargetKind::Lib,
+            "proc-macro" => TargetKind::ProcMacro,
+            "bin" => TargetKind::Bin,
+            "example" => TargetKind::Example,
+            "test" => TargetKind::Test,
+            "bench" => TargetKind::Bench,
+            "custom-build" => TargetKind::Build,
+            _ => TargetKind::Other,
+        }
+    }
+
+    fn cargo_kind(self) -> &'static str {
+        match self {
+            TargetKind::Lib => "lib",
+            TargetKind::ProcMacro => "proc-macro",
+            TargetKind::Bin => "bin",
+            TargetKind::Example => "example",
+            TargetKind::Test => "test",
+            TargetKind::Bench => "bench",
+            TargetKind::Build => "custom-build",
+            TargetKind::Other => "other",
+        }
+    }
+}
+
+fn target(kind: TargetKind, name: &str, src_path: &str) -> CrateTarget {
+    CrateTarget {
+        name: name.to_string(),
+        kinds: vec![kind.cargo_kind().to_string()],
+        src_path: src_path.to_string(),
+    }
+}
+
+// `<dir>/<name>.rs` and `<dir>/<name>/main.rs` files, as Cargo discovers binaries,
+// examples, tests and benches
+fn discover_dir(crate_dir: &Path, dir: &str) -> Vec<(String, String)> {
+    let mut found = Vec::new();
+    let Ok(entries) = fs::read_dir(crate_dir.join(dir)) else {
+        return found;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if path.is_dir() && path.join("main.rs").is_file() {
+            found.push((name.clone(), format!("{}/{}/main.rs", dir, name)));
+        } else if let Some(stem) = name.strip_suffix(".rs").filter(|_| path.is_file()) {
+            found.push((stem.to_string(), format!("{}/{}", dir, name)));
+        }
+    }
+    found.sort();
+    found
+}
+
+// Targets declared in the manifest, followed by the discovered ones they don't replace
+fn add_targets(
+    targets: &mut Vec<CrateTarget>,
+    kind: TargetKind,
+    declared: &[CargoTomlTarget],
+    discovered: Vec<(String, String)>,
+    crate_dir: &Path,
+    dir: &str,
+) {
+    let first = targets.len();
+    for declared in declared {
+        let name = declared.name.clone().unwrap_or_default();
+        let src_path = match &declared.path {
+            Some(path) => path.clone(),
+            None if crate_dir.join(dir).join(&name).join("main.rs").is_file() => {
+                format!("{}/{}/main.rs", dir, name)
+            }
+            None => format!("{}/{}.rs", dir, name),
+        };
+        targets.push(target(kind, &name, &src_path));
+    }
+    for (name, src_path) in discovered {
+        if !targets[first..]
+            .iter()
+            .any(|t| t.name == name || t.src_path == src_path)
+        {
+            targets.push(target(kind, &name, &src_path));
+        }
+    }
+}
+
+/// Finds the targets of a crate from its manifest, following Cargo's target
+/// auto-discovery for the ones it doesn't declare
+pub fn discover_targets(crate_dir: &Path) -> Vec<CrateTarget> {
+    let manifest = fs::read_to_string(crate_dir.join("Cargo.toml")).unwrap_or_default();
+    let manifest: CargoToml = toml::from_str(&manifest).unwrap_or_default();
+    let package = manifest.package.unwrap_or_default();
+    let mut targets = Vec::new();
+
+    let lib_path = manifest
+        .lib
+        .as_ref()
+        .and_then(|lib| lib.path.clone())
+        .unwrap_or("src/lib.rs".to_string());
+    if manifest.lib.is_some() || crate_dir.join(&lib_path).is_file() {
+        let kind = match manifest.lib.as_ref().is_some_and(|lib| lib.proc_macro) {
+            true => TargetKind::ProcMacro,
+            false => TargetKind::Lib,
+        };
+        targets.push(target(kind, &package.name, &lib_path));
+    }
+
+    let mut bins = Vec::new();
+    if package.autobins != Some(false) {
+        if crate_dir.join("src/main.rs").is_file() {
+            bins.push((package.name.clone(), "src/main.rs".to_string()));
+        }
+        bins.extend(discover_dir(crate_dir, "src/bin"));
+    }
+    let declared = manifest.bin.unwrap_or_default();
+    add_targets(
+        &mut targets,
+        TargetKind::Bin,
+        &declared,
+        bins,
+        crate_dir,
+        "src/bin",
+    );
+
+    for (kind, dir, declared, auto) in [
+        (
+            TargetKind::Example,
+            "examples",
+            manifest.example,
+            package.autoexamples,
+        ),
+        (TargetKind::Test, "tests", manifest.test, package.autotests),
+        (
+            TargetKind::Bench,
+            "benches",
+            manifest.bench,
+            package.autobenches,
+        ),
+    ] {
+        let discovered = match auto {
+            Some(false) => Vec::new(),
+            _ => discover_dir(crate_dir, dir),
+        };
+        let declared = declared.unwrap_or_default();
+        add_targets(&mut targets, kind, &declared, discovered, crate_dir, dir);
+    }
+
+    let build = match package.build {
+        Some(toml::Value::String(path)) => Some(path),
+        Some(toml::Value::Boolean(false)) => None,
+        _ => crate_dir
+            .join("build.rs")
+            .is_file()
+            .then(|| "build.rs".to_string()),
+    };
+    if let Some(build) = build {
+        targets.push(target(TargetKind::Build, "build-script-build", &build));
+    }
+    targets
+}
+
+/// Targets reported by `cargo metadata`, or discovered like Cargo does
+pub fn crate_targets(crate_dir: &Path, package: Option<&CratePackage>) -> Vec<CrateTarget> {
+    match package {
+        Some(package) => package.targets.clone(),
+        None => discover_targets(crate_dir),
+    }
+}
+
+/// Assigns the source files of a crate to its targets
+pub struct CrateTargets {
+    // Kind, root file and directory of the modules of every target
+    targets: Vec<(TargetKind, PathBuf, PathBuf)>,
+}
+
+impl CrateTargets {
+    pub fn new(crate_dir: &Path, targets: &[CrateTarget]) -> Self {
+        let targets = targets
+            .iter()
+            .map(|target| {
+                let kind = TargetKind::from_cargo_kinds(&target.kinds);
+                let root = crate_dir.join(&target.src_path);
+                // Modules of a crate root are next to it. The build script is
+                // usually at the root of the crate, so it only gets its own file
+                let modules_dir = match kind {
+                    TargetKind::Build => root.clone(),
+                    _ => root.parent().unwrap_or(crate_dir).to_path_buf(),
+                };
+                (kind, root, modules_dir)
+            })
+            .collect();
+        Self { targets }
+    }
+
+    /// Kind of the target whose modules directory is the closest parent of the
+    /// file. The library wins over binaries sharing `src`.
+    pub fn kind_of(&self, file: &Path) -> TargetKind {
+        if let Some((kind, _, _)) = self.targets.iter().find(|(_, root, _)| root == file) {
+            return *kind;
+        }
+        self.targets
+            .iter()
+            .filter(|(_, _, modules_dir)| file.starts_with(modules_dir))
+            .min_by_key(|(kind, _, modules_dir)| {
+                (std::cmp::Reverse(modules_dir.components().count()), *kind)
+            })
+            .map_or(TargetKind::Other, |(kind, _, _)| *kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Crate of the files in a temporary directory
+    fn crate_dir(files: &[(&str, &str)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, content) in files {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    fn targets(crate_dir: &Path) -> Vec<(TargetKind, String, String)> {
+        discover_targets(crate_dir)
+            .into_iter()
+            .map(|target| {
+                let kind = TargetKind::from_cargo_kinds(&target.kinds);
+                (kind, target.name, target.src_path)
+            })
+            .collect()
+    }
+
+    fn expected(targets: &[(TargetKind, &str, &str)]) -> Vec<(TargetKind, String, String)> {
+        targets
+            .iter()
+            .map(|(kind, name, src_path)| (*kind, name.to_string(), src_path.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn discovers_undeclared_targets() {
+        let dir = crate_dir(&[
+            (
+                "Cargo.toml",
+                "[package]\nname = \"foo\"\n\n[[bin]]\nname = \"tool\"\n",
+            ),
+            ("src/lib.rs", ""),
+            ("src/main.rs", ""),
+            ("src/bin/tool.rs", ""),
+            ("src/bin/multi/main.rs", ""),
+            ("examples/demo.rs", ""),
+            ("tests/it.rs", ""),
+            ("benches/bench.rs", ""),
+            ("build.rs", ""),
+        ]);
+        assert_eq!(
+            targets(dir.path()),
+            expected(&[
+                (TargetKind::Lib, "foo", "src/lib.rs"),
+                (TargetKind::Bin, "tool", "src/bin/tool.rs"),
+                (TargetKind::Bin, "foo", "src/main.rs"),
+                (TargetKind::Bin, "multi", "src/bin/multi/main.rs"),
+                (TargetKind::Example, "demo", "examples/demo.rs"),
+                (TargetKind::Test, "it", "tests/it.rs"),
+                (TargetKind::Bench, "bench", "benches/bench.rs"),
+                (TargetKind::Build, "build-script-build", "build.rs"),
+            ])
+        );
+    }
+
+    #[test]
+    fn keeps_only_declared_targets_when_discovery_is_disabled() {
+        let dir = crate_dir(&[
+            (
+                "Cargo.toml",
+                "[package]\nname = \"foo\"\nautobins = false\nbuild = false\n\n\
+                 [lib]\nproc-macro = true\npath = \"lib.rs\"\n\n\
+                 [[bin]]\nname = \"cli\"\n\n[[bin]]\nname = \"single\"\n",
+            ),
+            ("lib.rs", ""),
+            ("src/main.rs", ""),
+            ("src/bin/tool.rs", ""),
+            ("src/bin/cli/main.rs", ""),
+            ("build.rs", ""),
+        ]);
+        assert_eq!(
+            targets(dir.path()),
+            expected(&[
+                (TargetKind::ProcMacro, "foo", "lib.rs"),
+                (TargetKind::Bin, "cli", "src/bin/cli/main.rs"),
+                (TargetKind::Bin, "single", "src/bin/single.rs"),
+            ])
+        );
+    }
+
+    #[test]
+    fn assigns_files_to_the_closest_target() {
+        let dir = crate_dir(&[
+            ("Cargo.toml", "[package]\nname = \"foo\"\n"),
+            ("src/lib.rs", ""),
+            ("src/main.rs", ""),
+            ("src/bin/multi/main.rs", ""),
+            ("tests/it.rs", ""),
+            ("build.rs", ""),
+        ]);
+        let targets = CrateTargets::new(dir.path(), &discover_targets(dir.path()));
+        let kind_of = |path: &str| targets.kind_of(&dir.path().join(path));
+
+        assert_eq!(kind_of("src/main.rs"), TargetKind::Bin);
+        assert_eq!(kind_of("src/lib.rs"), TargetKind::Lib);
+        // Shared by the library and the binary
+        assert_eq!(kind_of("src/utils/mod.rs"), TargetKind::Lib);
+        assert_eq!(kind_of("src/bin/multi/cli.rs"), TargetKind::Bin);
+        assert_eq!(kind_of("tests/common/mod.rs"), TargetKind::Test);
+        assert_eq!(kind_of("build.rs"), TargetKind::Build);
+        assert_eq!(kind_of("fixtures/input.rs"), TargetKind::Other);
+    }
+}
@@ -2,12 +2,6 @@ use std::fmt::Debug;
 
 use tree_sitter::{Parser, Tree};
 
-const DATA_PATH: &str = "./data";
-
-pub fn create_data_folder() {
-    std::fs::create_dir_all(DATA_PATH).unwrap();
-}
-
 pub fn parse_file(bytes: &[u8]) -> Tree {
     let mut parser = Parser::new();
     parser
@@ -16,34 +10,22 @@ pub fn parse_file(bytes: &[u8]) -> Tree {
     parser.parse(bytes, None).expect("Failed to parse file")
 }
 
-pub fn remove_data_prefix(s: &str) -> String {
-    let prefixes = ["./data/repos/", "data/repos/"];
-    
-    for prefix in &prefixes {
-        match s.starts_with(prefix) {
-            true => {
-                return s[prefix.len()..].to_owned();
-            }
-            false => (),
-        }
-    }
-    
-    s.to_owned()
-}
-
 macro_rules! impl_save_load {
     ($struct_name:ident, $path:expr) => {
         impl $struct_name {
-            pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
-                let mut file = std::fs::File::create($path)?;
+            pub fn save(
+                &self,
+                workspace: &crate::workspace::Workspace,
+            ) -> Result<(), Box<dyn std::error::Error>> {
+                let mut file = std::fs::File::create($path(workspace))?;
                 let ron_string =
                     ron::ser::to_string_pretty(&self, ron::ser::PrettyConfig::default())?;
                 std::io::Write::write_all(&mut file, ron_string.as_bytes())?;
                 Ok(())
             }
 
-            pub fn load() -> Option<Self> {
-                let mut state_file = match std::fs::File::open($path) {
+            pub fn load(workspace: &crate::workspace::Workspace) -> Option<Self> {
+                let mut state_file = match std::fs::File::open($path(workspace)) {
                     Ok(file) => file,
                     Err(_) => return None,
                 };
@@ -83,8 +65,6 @@ pub fn pretty_print(title: &str, description: Option<&dyn Debug>) {
     println!("{}", output);
 }
 
-pub const FOLDERS_TO_IGNORE: [&str; 2] = ["target", "malformed"];
-pub const FILES_TO_IGNORE: [&str; 2] = ["package_loader.rs", ".macro-expanded.rs"];
 pub const BUILTIN_ATTRIBUTES: [&str; 48] = [
     "cfg",
     "cfg_attr",
@@ -135,3 +115,29 @@ pub const BUILTIN_ATTRIBUTES: [&str; 48] = [
     "non_exhaustive",
     "debugger_visualizer",
 ];
+
+// Inert attributes read by widely used derive macros, e.g. `#[serde(rename = "..")]`
+pub const DERIVE_HELPER_ATTRIBUTES: [&str; 22] = [
+    "default",
+    "serde",
+    "clap",
+    "command",
+    "arg",
+    "value",
+    "group",
+    "structopt",
+    "error",
+    "from",
+    "source",
+    "backtrace",
+    "builder",
+    "strum",
+    "sqlx",
+    "schemars",
+    "validate",
+    "ts",
+    "diesel",
+    "sea_orm",
+    "prost",
+    "darling",
+];
@@ -0,0 +1,165 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use crate::{crate_paths::get_repo_path, github::GRAPHQL_URL, ignore::IGNORE_FILE_NAME};
+
+pub const DEFAULT_DATA_PATH: &str = "./data";
+
+/// Root directory holding every artifact of a dataset, so that multiple
+/// datasets can be kept side by side
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    root: PathBuf,
+    // Repositories analyzed in place instead of being cloned, by name
+    local_repos: BTreeMap<String, PathBuf>,
+    graphql_url: String,
+}
+
+impl Default for Workspace {
+    fn default() -> Self {
+        Self::new(DEFAULT_DATA_PATH)
+    }
+}
+
+impl Workspace {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            local_repos: BTreeMap::new(),
+            graphql_url: GRAPHQL_URL.to_string(),
+        }
+    }
+
+    /// Sends the GitHub API requests to another endpoint, such as the `mock-github` responder
+    pub fn with_graphql_url(mut self, graphql_url: impl Into<String>) -> Self {
+        self.graphql_url = graphql_url.into();
+        self
+    }
+
+    pub fn graphql_url(&self) -> &str {
+        &self.graphql_url
+    }
+
+    /// Registers local directories as repositories, named after the directory
+    pub fn with_local_repos(mut self, dirs: &[PathBuf]) -> Result<Self, Box<dyn Error>> {
+        for dir in dirs {
+            let dir = dir.canonicalize()?;
+            let name = match dir.file_name() {
+                Some(name) => name.to_string_lossy().to_string(),
+                None => return Err(format!("Invalid repository directory {:?}", dir).into()),
+            };
+            if let Some(prev) = self.local_repos.insert(name.clone(), dir) {
+                return Err(
+                    format!("Repository name {} is already used by {:?}", name, prev).into(),
+                );
+            }
+        }
+        Ok(self)
+    }
+
+    pub fn local_repos(&self) -> impl Iterator<Item = &PathBuf> {
+        self.local_repos.values()
+    }
+
+    pub fn create_folder(&self) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.root)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn state_path(&self) -> PathBuf {
+        self.root.join("state.ron")
+    }
+
+    pub fn popular_repos_path(&self) -> PathBuf {
+        self.root.join("repos.ron")
+    }
+
+    /// Commits the repositories were cloned at
+    pub fn manifest_path(&self) -> PathBuf {
+        self.root.join("manifest.ron")
+    }
+
+    /// Clone status of every repository
+    pub fn clone_report_path(&self) -> PathBuf {
+        self.root.join("clone_status.ron")
+    }
+
+    /// crates.io crates unpacked instead of cloning repositories
+    pub fn published_crates_path(&self) -> PathBuf {
+        self.root.join("published_crates.ron")
+    }
+
+    pub fn crate_paths_path(&self) -> PathBuf {
+        self.root.join("crates.ron")
+    }
+
+    /// Packages of the crates found with `cargo metadata`
+    pub fn crate_packages_path(&self) -> PathBuf {
+        self.root.join("packages.ron")
+    }
+
+    pub fn results_path(&self) -> PathBuf {
+        self.root.join("analyzis.ron")
+    }
+
+    pub fn previous_results_path(&self) -> PathBuf {
+        self.root.join("analyzis.previous.ron")
+    }
+
+    pub fn ranges_to_remove_path(&self) -> PathBuf {
+        self.root.join("cfg_ranges.ron")
+    }
+
+    /// Ignore patterns applied to every repository
+    pub fn ignore_path(&self) -> PathBuf {
+        self.root.join(IGNORE_FILE_NAME)
+    }
+
+    pub fn data_json_path(&self) -> PathBuf {
+        self.root.join("data.json")
+    }
+
+    /// Directory where the repositories are cloned into
+    pub fn repos_path(&self) -> PathBuf {
+        self.root.join("repos")
+    }
+
+    /// Copy of the repositories without conditionally compiled code
+    pub fn parsed_repos_path(&self) -> PathBuf {
+        self.root.join("parsed_repos")
+    }
+
+    pub fn repo_dir(&self, repo_path: &str) -> PathBuf {
+        match self.local_repos.get(repo_path) {
+            Some(dir) => dir.clone(),
+            None => self.repos_path().join(repo_path),
+        }
+    }
+
+    pub fn crate_dir(&self, crate_path: &str) -> PathBuf {
+        let repo_path = get_repo_path(crate_path);
+        let relative = crate_path[repo_path.len()..].trim_start_matches('/');
+        self.repo_dir(&repo_path).join(relative)
+    }
+
+    /// Path of the file or directory relative to the cloned repositories folder
+    pub fn relative_repo_path(&self, path: &Path) -> String {
+        for (name, dir) in &self.local_repos {
+            if let Ok(relative) = path.strip_prefix(dir) {
+                if relative.as_os_str().is_empty() {
+                    return name.clone();
+                }
+                return Path::new(name).join(relative).to_string_lossy().to_string();
+            }
+        }
+        let repos_path = self.repos_path();
+        let relative = path.strip_prefix(&repos_path).unwrap_or(path);
+        relative.to_string_lossy().to_string()
+    }
+}